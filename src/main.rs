@@ -1,27 +1,46 @@
 #![windows_subsystem = "windows"]
 
-mod util;
-
-use util::{
-    discord::DiscordClient,
+use roblox_discord_presence::util::{
+    clipboard,
+    discord::{ApplicationIds, DiscordClient, DiscordStatus, Presence},
     log_parser::LogMonitor,
     paths::RobloxType,
+    presence,
     watcher::{self, WatcherEvent},
-    roblox_api,
+    roblox_api::{self, GameDetails, GameId, PlaceId, UniverseId},
     notifier,
+    replay::{self, ReplayOptions},
+    session::{unix_time, Place, Session, SessionChange, SessionTracker},
     settings::Settings,
     tray,
 };
 
+use tokio::sync::mpsc::UnboundedReceiver;
 use tokio::time::{interval, Duration};
 use tao::event_loop::{EventLoop, ControlFlow};
 use tray_icon::menu::MenuEvent;
 use auto_launch::AutoLaunchBuilder;
-use windows::Win32::System::Console::{AllocConsole, FreeConsole};
+use windows::Win32::System::Console::{AllocConsole, AttachConsole, FreeConsole, ATTACH_PARENT_PROCESS};
 use notify::{Config, RecommendedWatcher, RecursiveMode, Watcher};
-use std::sync::mpsc::channel;
+use std::collections::HashMap;
+use std::sync::mpsc::{channel, Sender};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
 
 fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(String::as_str) == Some("replay") {
+        // We're a windowed app, so print to the terminal we were started from
+        unsafe { let _ = AttachConsole(ATTACH_PARENT_PROCESS); }
+
+        let result = ReplayOptions::from_args(&args[2..]).and_then(|options| replay::run(&options));
+        if let Err(e) = result {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
     let event_loop = EventLoop::new();
     
     let mut settings = Settings::load();
@@ -30,7 +49,7 @@ fn main() {
         unsafe { let _ = AllocConsole(); }
     }
     
-    let mut tray_handles = Some(tray::setup_tray(&settings));
+    let tray_handles = Some(tray::setup_tray(&settings));
     
     let auto = AutoLaunchBuilder::new()
         .set_app_name("Roblox Discord Presence")
@@ -63,16 +82,26 @@ fn main() {
         let _ = watcher.watch(parent, RecursiveMode::NonRecursive);
     }
     
+    // Join link for the server we're currently in, shared with the tray menu
+    let server_link: Arc<Mutex<Option<String>>> = Arc::new(Mutex::new(None));
+    let async_server_link = server_link.clone();
+    let show_join_button = Arc::new(AtomicBool::new(settings.show_join_button));
+    let async_show_join_button = show_join_button.clone();
+    let (discord_status_tx, discord_status_rx) = channel();
+    // Settings the presence depends on, sent again whenever the file changes
+    let (settings_tx, settings_rx) = tokio::sync::mpsc::unbounded_channel();
+    let async_settings = settings.clone();
+
     // Spawn async runtime
-    std::thread::spawn(|| {
+    std::thread::spawn(move || {
         let rt = tokio::runtime::Runtime::new().unwrap();
-        rt.block_on(async_main());
+        rt.block_on(async_main(async_server_link, async_show_join_button, discord_status_tx, async_settings, settings_rx));
     });
 
     event_loop.run(move |_event, _, control_flow| {
         *control_flow = ControlFlow::WaitUntil(std::time::Instant::now() + std::time::Duration::from_millis(50));
 
-        if let Ok(_) = config_rx.try_recv() {
+        if config_rx.try_recv().is_ok() {
             println!("Config changed, reloading settings...");
             let new_settings = Settings::load();
             
@@ -102,16 +131,61 @@ fn main() {
                     }
                 }
             }
+            if new_settings.show_join_button != settings.show_join_button {
+                settings.show_join_button = new_settings.show_join_button;
+                if let Some(handles) = &tray_handles {
+                    handles.show_join_button.set_checked(settings.show_join_button);
+                }
+                show_join_button.store(settings.show_join_button, Ordering::Relaxed);
+            }
+            if new_settings.discord_application_id != settings.discord_application_id
+                || new_settings.studio_discord_application_id != settings.studio_discord_application_id
+                || new_settings.asset_keys != settings.asset_keys
+            {
+                settings.discord_application_id = new_settings.discord_application_id;
+                settings.studio_discord_application_id = new_settings.studio_discord_application_id;
+                settings.asset_keys = new_settings.asset_keys;
+                let _ = settings_tx.send(settings.clone());
+            }
             // Update other settings if needed
             // settings = new_settings; // Or just replace the whole struct if it were simple, but we handle side effects above
         }
 
+        if let Some(status) = discord_status_rx.try_iter().last() {
+            if let Some(handles) = &tray_handles {
+                let _ = handles.tray_icon.set_tooltip(Some(tray::status_tooltip(&status)));
+            }
+        }
+
         if let Ok(event) = MenuEvent::receiver().try_recv() {
             match event.id.as_ref() {
                 tray::MENU_QUIT_ID => {
                     *control_flow = ControlFlow::Exit;
                     std::process::exit(0);
                 }
+                tray::MENU_COPY_SERVER_LINK_ID => {
+                    let link = server_link.lock().unwrap().clone();
+                    match link {
+                        Some(link) => {
+                            if let Err(e) = clipboard::copy(&link) {
+                                notifier::error("Clipboard Error", &format!("Failed to copy server link: {}", e));
+                            }
+                        }
+                        None => {
+                            notifier::info("Server Link", "You are not in a joinable public server right now.");
+                        }
+                    }
+                }
+                tray::MENU_SHOW_JOIN_BUTTON_ID => {
+                    settings.show_join_button = !settings.show_join_button;
+                    let _ = settings.save();
+
+                    if let Some(handles) = &tray_handles {
+                        handles.show_join_button.set_checked(settings.show_join_button);
+                    }
+                    // Shown from the next presence update on
+                    show_join_button.store(settings.show_join_button, Ordering::Relaxed);
+                }
                 tray::MENU_OPEN_CONFIG_ID => {
                     let path = Settings::config_path();
                     if let Some(parent) = path.parent() {
@@ -129,7 +203,7 @@ fn main() {
                     #[cfg(target_os = "windows")]
                     {
                         let _ = std::process::Command::new("cmd")
-                            .args(&["/C", "start", "", &path.to_string_lossy()])
+                            .args(["/C", "start", "", &path.to_string_lossy()])
                             .spawn();
                     }
                     
@@ -196,37 +270,83 @@ fn main() {
     });
 }
 
-async fn async_main() {
+async fn async_main(
+    server_link: Arc<Mutex<Option<String>>>,
+    show_join_button: Arc<AtomicBool>,
+    discord_status_tx: Sender<DiscordStatus>,
+    settings: Settings,
+    mut settings_rx: UnboundedReceiver<Settings>,
+) {
     let mut discord_client = DiscordClient::new();
+    discord_client.configure(application_ids(&settings), &settings.asset_keys);
+
+    let mut presence = PresenceState {
+        discord_client,
+        server_link,
+        show_join_button,
+        focused_pid: None,
+        current_game: None,
+        team_create: false,
+        sub_place_names: HashMap::new(),
+    };
     let mut log_monitor = LogMonitor::new();
-    
-    let mut current_roblox_type: Option<RobloxType> = None;
-    let mut last_place_id: String = String::new();
+    let mut trackers: HashMap<u32, SessionTracker> = HashMap::new();
 
     let mut event_receiver = watcher::spawn_watcher();
-    
+    let mut log_changes = log_monitor.watch_logs();
+
+    // Windows may hold back change events for a log Roblox keeps open until
+    // its cache flushes, so poll as often as without a watcher
     let mut log_poll_interval = interval(Duration::from_secs(2));
     log_poll_interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+
+    // Reconnects to Discord and sends updates held back by its rate limit
+    let mut discord_interval = interval(Duration::from_secs(1));
+    discord_interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
     
+    let mut discord_status = DiscordStatus::Disconnected;
+
     loop {
-        tokio::select! {
+        let logs_changed = tokio::select! {
             event = event_receiver.recv() => {
                 match event {
                     Some(WatcherEvent::RobloxStarted(rt)) => {
-                        last_place_id.clear(); 
-                        current_roblox_type = Some(rt);
-
-                        if rt == RobloxType::Studio {
-                            discord_client.update_presence("Roblox Studio", "Developing", "roblox_studio", None);
-                        } else {
-                            discord_client.update_presence("Roblox", "Loading", "roblox_logo", None);
+                        // Another client starting shouldn't replace a game in progress
+                        if presence.focused_pid.is_none() {
+                            presence.discord_client.set_roblox_type(rt);
+                            let started = if rt == RobloxType::Studio {
+                                Presence::new("Roblox Studio", "Developing", "roblox_studio")
+                            } else {
+                                Presence::new("Roblox", "Loading", "roblox_logo")
+                            };
+                            presence.discord_client.update_presence(&started.start_time(unix_time()));
                         }
+                        false
                     }
                     Some(WatcherEvent::RobloxClosed) => {
-                        discord_client.clear_presence();
+                        presence.discord_client.clear_presence();
+                        presence.focused_pid = None;
+                        presence.current_game = None;
+                        *presence.server_link.lock().unwrap() = None;
                         log_monitor.clear();
-                        current_roblox_type = None;
-                        last_place_id.clear();
+                        trackers.clear();
+                        false
+                    }
+                    Some(WatcherEvent::ProcessesChanged(processes)) => {
+                        trackers.retain(|pid, _| processes.iter().any(|p| p.pid == *pid));
+
+                        let focused_exited = presence
+                            .focused_pid
+                            .is_some_and(|pid| !processes.iter().any(|p| p.pid == pid));
+
+                        // If nothing is left running, RobloxClosed clears the presence
+                        if let (true, Some(latest)) = (focused_exited, processes.last()) {
+                            println!("[SESSION] Focused process exited");
+                            presence.refocus(&trackers, latest.roblox_type).await;
+                        }
+
+                        log_monitor.set_processes(processes);
+                        true
                     }
                     None => {
                         break;
@@ -234,60 +354,293 @@ async fn async_main() {
                 }
             }
 
+            Some(settings) = settings_rx.recv() => {
+                presence.discord_client.configure(application_ids(&settings), &settings.asset_keys);
+                false
+            }
+
+            _ = discord_interval.tick() => {
+                presence.discord_client.tick();
+                false
+            }
+
             _ = log_poll_interval.tick() => {
-                if let Some(roblox_type) = current_roblox_type {
-                    if let Some(id) = log_monitor.check_latest_log() {
-                        if id != last_place_id {
-                            last_place_id = id.clone();
-
-                            match roblox_api::get_game_details(&id).await {
-                                Ok(details) => {
-                                    match roblox_type {
-                                        RobloxType::Player => {
-                                            let state_str = format!("by {}", details.creator_name);
-                                            let stats_str = format!("Playing: {} | Capacity: {}", format_num(details.playing), details.max_players);
-                                            
-                                            discord_client.update_presence(
-                                                &details.name, 
-                                                &state_str,
-                                                &details.thumbnail_url,
-                                                Some(&stats_str) 
-                                            );
-                                        },
-                                        RobloxType::Studio => {
-                                            let state_str = "Editing";
-
-                                            discord_client.update_presence(
-                                                &details.name, 
-                                                state_str,
-                                                &details.thumbnail_url,
-                                                Some("Developing")
-                                            );
-                                        }
-                                    }
-                                }
-                                Err(e) => {
-                                    notifier::error("Game Details Error", &format!("Failed to fetch details: {}", e));
-                                },
+                log_monitor.rescan();
+                true
+            }
+
+            Some(path) = log_changes.recv() => {
+                log_monitor.log_changed(path);
+                true
+            }
+        };
+
+        if *presence.discord_client.status() != discord_status {
+            discord_status = presence.discord_client.status().clone();
+            let _ = discord_status_tx.send(discord_status.clone());
+        }
+
+        if !logs_changed {
+            continue;
+        }
+
+        for batch in log_monitor.check_logs() {
+            let pid = batch.process.pid;
+            let roblox_type = batch.process.roblox_type;
+            let tracker = trackers
+                .entry(pid)
+                .or_insert_with(|| SessionTracker::new(roblox_type));
+
+            let changes = if batch.catching_up {
+                println!("[LOGS] Caught up on {} events from process {}", batch.events.len(), pid);
+                tracker.catch_up(&batch.events).into_iter().collect()
+            } else {
+                batch.events
+                    .iter()
+                    .filter_map(|log_event| tracker.apply(log_event))
+                    .collect::<Vec<_>>()
+            };
+
+            for change in changes {
+                match change {
+                    SessionChange::Started(session) => {
+                        presence.start_session(pid, roblox_type, &session).await;
+                    }
+                    SessionChange::Teleported(session) => {
+                        // A teleport into another game counts as a new session
+                        if let Some(started_at) = presence.teleport(pid, roblox_type, &session).await {
+                            if let Some(tracker) = trackers.get_mut(&pid) {
+                                tracker.restart_timer(started_at);
                             }
                         }
                     }
+                    SessionChange::Updated(session) => {
+                        if presence.focused_pid == Some(pid) {
+                            presence.update_session(roblox_type, &session).await;
+                        }
+                    }
+                    SessionChange::TestStarted(session) => {
+                        presence.start_test(pid, roblox_type, &session).await;
+                    }
+                    SessionChange::TestStopped(session) => {
+                        if presence.focused_pid == Some(pid) {
+                            presence.stop_test(roblox_type, &session).await;
+                        }
+                    }
+                    SessionChange::Ended => {
+                        if presence.focused_pid == Some(pid) {
+                            println!("[SESSION] Ended");
+                            presence.focused_pid = None;
+                            presence.refocus(&trackers, roblox_type).await;
+                        }
+                    }
                 }
             }
         }
     }
 }
 
-fn format_num(n: u64) -> String {
-    let s = n.to_string();
-    let mut result = String::new();
-    let mut count = 0;
-    for c in s.chars().rev() {
-        if count > 0 && count % 3 == 0 {
-            result.push(',');
+/// What Discord is showing, and which Roblox process it's about.
+struct PresenceState {
+    discord_client: DiscordClient,
+    server_link: Arc<Mutex<Option<String>>>,
+    show_join_button: Arc<AtomicBool>,
+    focused_pid: Option<u32>,
+    current_game: Option<GameDetails>,
+    /// The focused Studio session was already shown as in Team Create.
+    team_create: bool,
+    /// Names of places looked up so far, as redraws would fetch them again.
+    sub_place_names: HashMap<PlaceId, String>,
+}
+
+impl PresenceState {
+    async fn start_session(&mut self, pid: u32, roblox_type: RobloxType, session: &Session) {
+        println!("[SESSION] Started in {} ({:?}) on process {}", session.place, session.server.job_id, pid);
+        self.focused_pid = Some(pid);
+        *self.server_link.lock().unwrap() = session.join_link();
+        self.current_game = None;
+        self.team_create = session.team_create;
+        self.discord_client.set_roblox_type(roblox_type);
+
+        match &session.place {
+            Place::Published(game_id) if roblox_type == RobloxType::Studio || !session.is_private() => {
+                let join_link = self.join_button_link(session);
+                self.load_game(roblox_type, *game_id, None, session, join_link).await;
+            }
+            // Local files and private servers don't need the game details
+            _ => {
+                if let Some(presence) = presence::session_presence(roblox_type, session, None, None, None) {
+                    self.discord_client.update_presence(&presence);
+                }
+            }
+        }
+    }
+
+    async fn update_session(&mut self, roblox_type: RobloxType, session: &Session) {
+        let join_link = session.join_link();
+        let link_changed = *self.server_link.lock().unwrap() != join_link;
+        *self.server_link.lock().unwrap() = join_link;
+
+        match roblox_type {
+            // Server details don't change what Studio shows, only joining Team Create does
+            RobloxType::Studio if session.team_create && !self.team_create => {
+                println!("[SESSION] Joined Team Create");
+                self.team_create = true;
+                self.show_session(roblox_type, session).await;
+            }
+            RobloxType::Studio => {}
+            // The job ID for the join button can arrive after the join started
+            RobloxType::Player if link_changed && self.show_join_button.load(Ordering::Relaxed) => {
+                self.show_session(roblox_type, session).await;
+            }
+            RobloxType::Player => {
+                if let Some(details) = &self.current_game {
+                    self.discord_client.update_party(presence::party_size(session, details));
+                }
+            }
+        }
+    }
+
+    /// Link for the "Join Server" button, if the user wants one shown.
+    fn join_button_link(&self, session: &Session) -> Option<String> {
+        if self.show_join_button.load(Ordering::Relaxed) {
+            session.join_link()
+        } else {
+            None
+        }
+    }
+
+    async fn start_test(&mut self, pid: u32, roblox_type: RobloxType, session: &Session) {
+        if self.focused_pid != Some(pid) {
+            return self.start_session(pid, roblox_type, session).await;
+        }
+
+        println!("[SESSION] Testing {} ({:?})", session.place, session.test.map(|test| test.mode));
+        self.show_session(roblox_type, session).await;
+    }
+
+    async fn stop_test(&mut self, roblox_type: RobloxType, session: &Session) {
+        println!("[SESSION] Test stopped, editing {}", session.place);
+        self.show_session(roblox_type, session).await;
+    }
+
+    /// Redraws the focused session from the game details already loaded.
+    async fn show_session(&mut self, roblox_type: RobloxType, session: &Session) {
+        let sub_place = match (session.game_id(), self.current_game.clone()) {
+            (Some(game_id), Some(details)) => self.sub_place_name(&details, game_id).await,
+            _ => None,
+        };
+        let join_link = self.join_button_link(session);
+
+        // Failed lookups have nothing more to show
+        let shown = presence::session_presence(roblox_type, session, self.current_game.as_ref(), sub_place.as_deref(), join_link);
+        if let Some(shown) = shown {
+            self.discord_client.update_presence(&shown);
+        }
+    }
+
+    /// Returns the restarted session start if the teleport led into another game.
+    async fn teleport(&mut self, pid: u32, roblox_type: RobloxType, session: &Session) -> Option<u64> {
+        // Our game details belong to whichever client we were showing, and
+        // only players teleport, always to a published place. Private servers
+        // get the same treatment as when joined directly.
+        let game_id = session
+            .game_id()
+            .filter(|_| self.focused_pid == Some(pid) && !session.is_private());
+        let Some(game_id) = game_id else {
+            self.start_session(pid, roblox_type, session).await;
+            return None;
+        };
+
+        println!("[SESSION] Teleported to {} ({:?})", session.place, session.server.job_id);
+        *self.server_link.lock().unwrap() = session.join_link();
+
+        let universe_id = roblox_api::resolve_universe_id(game_id).await.ok();
+        match self.current_game.clone() {
+            // Same game, so keep the title and timer and only show the new place
+            Some(details) if universe_id == Some(details.universe_id) => {
+                let sub_place = self.sub_place_name(&details, game_id).await;
+                let join_link = self.join_button_link(session);
+                show_game_details(&mut self.discord_client, roblox_type, &details, sub_place.as_deref(), session, join_link);
+                None
+            }
+            _ => {
+                let session = Session {
+                    started_at: unix_time(),
+                    ..session.clone()
+                };
+                let join_link = self.join_button_link(&session);
+                self.load_game(roblox_type, game_id, universe_id, &session, join_link).await;
+                Some(session.started_at)
+            }
+        }
+    }
+
+    /// Loads and shows the game, skipping the universe lookup if the caller
+    /// already resolved it.
+    async fn load_game(&mut self, roblox_type: RobloxType, game_id: GameId, universe_id: Option<UniverseId>, session: &Session, join_link: Option<String>) {
+        let details = match universe_id {
+            Some(universe_id) => roblox_api::get_universe_details(universe_id).await,
+            None => roblox_api::get_game_details(game_id).await,
+        };
+
+        self.current_game = match details {
+            Ok(details) => {
+                let sub_place = self.sub_place_name(&details, game_id).await;
+                show_game_details(&mut self.discord_client, roblox_type, &details, sub_place.as_deref(), session, join_link);
+                Some(details)
+            }
+            Err(e) => {
+                notifier::error("Game Details Error", &format!("Failed to fetch details: {}", e));
+                None
+            }
+        };
+    }
+
+    /// Name of the place inside the game, if it isn't the game's start place.
+    async fn sub_place_name(&mut self, details: &GameDetails, game_id: GameId) -> Option<String> {
+        let place_id = match game_id {
+            GameId::Place(place_id) if place_id != details.root_place_id => place_id,
+            _ => return None,
+        };
+
+        if let Some(name) = self.sub_place_names.get(&place_id) {
+            return Some(name.clone());
+        }
+
+        let name = roblox_api::get_place_name(place_id).await.ok()?;
+        self.sub_place_names.insert(place_id, name.clone());
+        Some(name)
+    }
+
+    /// Shows the client that most recently started a session, or the idle
+    /// state if none is in one.
+    async fn refocus(&mut self, trackers: &HashMap<u32, SessionTracker>, idle_type: RobloxType) {
+        let other = trackers
+            .iter()
+            .filter_map(|(pid, tracker)| Some((*pid, tracker.roblox_type(), tracker.current()?.clone())))
+            .max_by_key(|(pid, _, session)| (session.started_at, *pid));
+
+        if let Some((pid, roblox_type, session)) = other {
+            return self.start_session(pid, roblox_type, &session).await;
         }
-        result.push(c);
-        count += 1;
+
+        self.focused_pid = None;
+        self.current_game = None;
+        *self.server_link.lock().unwrap() = None;
+
+        self.discord_client.set_roblox_type(idle_type);
+        self.discord_client.update_presence(&presence::idle_presence(idle_type, unix_time()));
     }
-    result.chars().rev().collect()
+}
+
+fn application_ids(settings: &Settings) -> ApplicationIds {
+    ApplicationIds::new(
+        settings.discord_application_id.as_deref(),
+        settings.studio_discord_application_id.as_deref(),
+    )
+}
+
+fn show_game_details(discord_client: &mut DiscordClient, roblox_type: RobloxType, details: &GameDetails, sub_place: Option<&str>, session: &Session, join_link: Option<String>) {
+    discord_client.update_presence(&presence::game_presence(roblox_type, details, sub_place, session, join_link));
 }
@@ -0,0 +1,26 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+pub fn copy(text: &str) -> std::io::Result<()> {
+    #[cfg(target_os = "windows")]
+    let mut command = Command::new("clip");
+
+    #[cfg(target_os = "macos")]
+    let mut command = Command::new("pbcopy");
+
+    #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+    let mut command = {
+        let mut command = Command::new("xclip");
+        command.args(["-selection", "clipboard"]);
+        command
+    };
+
+    let mut child = command.stdin(Stdio::piped()).spawn()?;
+
+    if let Some(stdin) = child.stdin.as_mut() {
+        stdin.write_all(text.as_bytes())?;
+    }
+
+    child.wait()?;
+    Ok(())
+}
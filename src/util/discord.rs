@@ -1,118 +1,431 @@
-use discord_rich_presence::{activity, DiscordIpc, DiscordIpcClient};
-use std::time::{SystemTime, UNIX_EPOCH};
+use serde_json::{json, Map, Value};
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use super::discord_ipc::{IpcError, IpcTransport, PresenceTransport, ReadyInfo};
+use super::notifier;
+use super::paths::RobloxType;
 
 const APP_ID: &str = "1442858852730277890";
+// Discord drops the whole activity if it has more buttons than this
+const MAX_BUTTONS: usize = 2;
+const RETRY_DELAY_MIN: Duration = Duration::from_secs(2);
+const RETRY_DELAY_MAX: Duration = Duration::from_secs(120);
+// Writes are the only way to notice Discord closing, so ping when idle
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+// Discord's Game SDK docs limit activity updates to 5 per 20 seconds
+const RATE_LIMIT_UPDATES: usize = 5;
+const RATE_LIMIT_WINDOW: Duration = Duration::from_secs(20);
+
+/// A link shown below the activity on the user's profile.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ActivityButton {
+    pub label: String,
+    pub url: String,
+}
+
+impl ActivityButton {
+    pub fn new(label: impl Into<String>, url: impl Into<String>) -> Self {
+        ActivityButton {
+            label: label.into(),
+            url: url.into(),
+        }
+    }
+}
+
+/// How many players are in the user's server, shown as "(current of max)".
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PartySize {
+    pub current: u32,
+    pub max: u32,
+}
+
+/// Everything the activity shows.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Presence {
+    pub details: String,
+    pub state: String,
+    pub large_image: String,
+    pub small_text: Option<String>,
+    /// Unix time the "elapsed" timer counts from.
+    pub start_time: Option<u64>,
+    pub party: Option<PartySize>,
+    pub buttons: Vec<ActivityButton>,
+}
+
+impl Presence {
+    pub fn new(details: &str, state: &str, large_image: &str) -> Self {
+        Presence {
+            details: details.to_string(),
+            state: state.to_string(),
+            large_image: large_image.to_string(),
+            ..Presence::default()
+        }
+    }
+
+    pub fn small_text(mut self, small_text: &str) -> Self {
+        self.small_text = Some(small_text.to_string());
+        self
+    }
+
+    pub fn start_time(mut self, start_time: u64) -> Self {
+        self.start_time = Some(start_time);
+        self
+    }
+
+    pub fn party(mut self, party: Option<PartySize>) -> Self {
+        self.party = party;
+        self
+    }
+
+    pub fn buttons(mut self, buttons: Vec<ActivityButton>) -> Self {
+        self.buttons = buttons;
+        self
+    }
+
+    /// The activity object of a SET_ACTIVITY command, with image keys
+    /// renamed through `asset_keys`.
+    pub fn to_activity(&self, asset_keys: &HashMap<String, String>) -> Value {
+        let asset = |key: &str| asset_keys.get(key).map_or(key, String::as_str).to_string();
+
+        let mut assets = json!({
+            "large_image": asset(&self.large_image),
+            "large_text": self.details,
+        });
+        if let Some(small_text) = &self.small_text {
+            assets["small_image"] = json!(asset("roblox_logo"));
+            assets["small_text"] = json!(small_text);
+        }
+
+        let mut activity = Map::new();
+        activity.insert("details".into(), json!(self.details));
+        activity.insert("state".into(), json!(self.state));
+        activity.insert("assets".into(), assets);
+
+        if let Some(start_time) = self.start_time {
+            activity.insert("timestamps".into(), json!({ "start": start_time }));
+        }
+        if let Some(party) = self.party {
+            activity.insert("party".into(), json!({ "size": [party.current, party.max] }));
+        }
+        if !self.buttons.is_empty() {
+            let buttons: Vec<Value> = self
+                .buttons
+                .iter()
+                .take(MAX_BUTTONS)
+                .map(|button| json!({ "label": button.label, "url": button.url }))
+                .collect();
+            activity.insert("buttons".into(), json!(buttons));
+        }
 
+        Value::Object(activity)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DiscordStatus {
+    Disconnected,
+    Connected(ReadyInfo),
+    /// Discord refused our application ID, so retrying won't help until it changes.
+    Rejected(String),
+}
+
+/// Discord application to show for each kind of client. Discord shows the
+/// application name as "Playing ...", so Studio can get a name of its own.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ApplicationIds {
+    pub player: String,
+    pub studio: String,
+}
+
+impl ApplicationIds {
+    /// Blank IDs use the built-in application, and Studio falls back to the
+    /// player one.
+    pub fn new(player: Option<&str>, studio: Option<&str>) -> Self {
+        let non_blank = |id: Option<&str>| id.map(str::trim).filter(|id| !id.is_empty()).map(str::to_string);
+        let player = non_blank(player).unwrap_or_else(|| APP_ID.to_string());
+        let studio = non_blank(studio).unwrap_or_else(|| player.clone());
+
+        ApplicationIds { player, studio }
+    }
+
+    pub fn get(&self, roblox_type: RobloxType) -> &str {
+        match roblox_type {
+            RobloxType::Player => &self.player,
+            RobloxType::Studio => &self.studio,
+        }
+    }
+}
+
+impl Default for ApplicationIds {
+    fn default() -> Self {
+        Self::new(None, None)
+    }
+}
+
+/// Keeps Discord showing the latest presence, reconnecting with backoff
+/// whenever Discord isn't running or restarts.
 pub struct DiscordClient {
-    client: Option<DiscordIpcClient>,
+    transport: Box<dyn PresenceTransport>,
+    app_ids: ApplicationIds,
+    roblox_type: RobloxType,
+    app_id: String,
+    asset_keys: HashMap<String, String>,
     connected: bool,
-    start_time: u64,
+    status: DiscordStatus,
+    /// What Discord should be showing, replayed after reconnecting.
+    presence: Option<Presence>,
+    /// `presence` hasn't reached Discord yet. Updates made while waiting for
+    /// the rate limit replace each other, so only the latest one is sent.
+    pending: bool,
+    /// When the updates still inside the rate limit window were sent, oldest first.
+    recent_updates: VecDeque<Instant>,
+    rate_limit: (usize, Duration),
+    /// Shortest and longest wait between connection attempts.
+    retry_delays: (Duration, Duration),
+    retry_delay: Duration,
+    next_attempt: Instant,
+    last_write: Instant,
 }
 
 impl DiscordClient {
     pub fn new() -> Self {
-        let start_time = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .expect("Time went backwards")
-            .as_secs();
-
-        let client = match DiscordIpcClient::new(APP_ID) {
-            Ok(c) => Some(c),
-            Err(e) => {
-                eprintln!("Failed to initialize Discord IPC client structure: {}", e);
-                None
-            }
-        };
+        Self::with_transport(APP_ID, Box::new(IpcTransport::new(APP_ID)))
+    }
 
+    pub fn with_transport(app_id: &str, transport: Box<dyn PresenceTransport>) -> Self {
         Self {
-            client,
+            transport,
+            app_ids: ApplicationIds::new(Some(app_id), None),
+            roblox_type: RobloxType::Player,
+            app_id: app_id.to_string(),
+            asset_keys: HashMap::new(),
             connected: false,
-            start_time,
+            status: DiscordStatus::Disconnected,
+            presence: None,
+            pending: false,
+            recent_updates: VecDeque::new(),
+            rate_limit: (RATE_LIMIT_UPDATES, RATE_LIMIT_WINDOW),
+            retry_delays: (RETRY_DELAY_MIN, RETRY_DELAY_MAX),
+            retry_delay: RETRY_DELAY_MIN,
+            next_attempt: Instant::now(),
+            last_write: Instant::now(),
         }
     }
 
+    /// Allows `updates` activity updates per `window` instead of Discord's limit.
+    pub fn with_rate_limit(mut self, updates: usize, window: Duration) -> Self {
+        self.rate_limit = (updates, window);
+        self
+    }
+
+    /// Backs off from `min` up to `max` between failed connection attempts.
+    pub fn with_retry_delays(mut self, min: Duration, max: Duration) -> Self {
+        self.retry_delays = (min, max);
+        self.retry_delay = min;
+        self
+    }
+
+    /// Switches to other Discord applications and image keys, moving the
+    /// current presence over.
+    pub fn configure(&mut self, app_ids: ApplicationIds, asset_keys: &HashMap<String, String>) {
+        let app_id = app_ids.get(self.roblox_type).to_string();
+        self.app_ids = app_ids;
+
+        if app_id == self.app_id && *asset_keys == self.asset_keys {
+            return;
+        }
+
+        self.asset_keys = asset_keys.clone();
+        self.switch_application(&app_id);
+
+        if self.presence.is_some() {
+            self.pending = true;
+            self.flush();
+        }
+    }
+
+    /// Shows the presence as the application for this kind of client from
+    /// now on. The old application's activity goes away with its connection.
+    pub fn set_roblox_type(&mut self, roblox_type: RobloxType) {
+        self.roblox_type = roblox_type;
+
+        let app_id = self.app_ids.get(roblox_type).to_string();
+        if app_id != self.app_id {
+            self.switch_application(&app_id);
+            self.presence = None;
+            self.pending = false;
+        }
+    }
+
+    fn switch_application(&mut self, app_id: &str) {
+        if app_id == self.app_id {
+            return;
+        }
+
+        println!("[DISCORD] Switching to application {}", app_id);
+        self.close();
+        self.app_id = app_id.to_string();
+        self.transport.set_client_id(app_id);
+        self.status = DiscordStatus::Disconnected;
+        self.retry_delay = self.retry_delays.0;
+        self.next_attempt = Instant::now();
+    }
+
     fn ensure_connected(&mut self) -> bool {
         if self.connected {
             return true;
         }
 
-        if let Some(client) = self.client.as_mut() {
-            match client.connect() {
-                Ok(_) => {
-                    println!("Connected to Discord");
-                    self.connected = true;
-                    return true;
-                }
-                Err(_) => {
-                    return false;
+        if Instant::now() < self.next_attempt {
+            return false;
+        }
+
+        match self.transport.connect() {
+            Ok(ready) => {
+                println!(
+                    "[DISCORD] Connected as {} ({}) on {}",
+                    ready.username,
+                    ready.user_id,
+                    ready.environment.as_deref().unwrap_or("unknown environment")
+                );
+                self.connected = true;
+                self.status = DiscordStatus::Connected(ready);
+                self.retry_delay = self.retry_delays.0;
+                self.last_write = Instant::now();
+                true
+            }
+            Err(e) if e.is_invalid_client_id() => {
+                let message = format!("Discord rejected application ID {}: {}", self.app_id, e);
+                // Only tell the user once, not on every retry
+                if !matches!(self.status, DiscordStatus::Rejected(_)) {
+                    notifier::error("Discord Error", &message);
                 }
+                self.status = DiscordStatus::Rejected(message);
+                self.next_attempt = Instant::now() + self.retry_delays.1;
+                false
             }
+            Err(e) => {
+                println!(
+                    "[DISCORD] Failed to connect ({}), retrying in {}s",
+                    e,
+                    self.retry_delay.as_secs()
+                );
+                self.status = DiscordStatus::Disconnected;
+                self.next_attempt = Instant::now() + self.retry_delay;
+                self.retry_delay = (self.retry_delay * 2).min(self.retry_delays.1);
+                false
+            }
+        }
+    }
+
+    fn handle_error(&mut self, error: IpcError) {
+        // A rejected activity leaves the connection usable
+        if let IpcError::Rejected { .. } = error {
+            eprintln!("Failed to set activity: {}", error);
+            return;
         }
 
-        false
+        eprintln!("Lost connection to Discord (Discord might have closed): {}", error);
+        self.close();
+        // Try again right away in case Discord is only restarting
+        self.retry_delay = self.retry_delays.0;
+        self.next_attempt = Instant::now();
     }
 
-    fn reset_start_time(&mut self) {
-        self.start_time = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .expect("Time went backwards")
-            .as_secs();
+    /// Drives reconnection and delivers updates held back by the rate
+    /// limit; call this regularly.
+    pub fn tick(&mut self) {
+        if self.connected && !self.pending && self.last_write.elapsed() >= HEALTH_CHECK_INTERVAL {
+            self.last_write = Instant::now();
+            if let Err(e) = self.transport.ping() {
+                self.handle_error(e);
+            }
+        }
+
+        let restoring = !self.connected && self.pending && self.presence.is_some();
+        self.flush();
+        if restoring && self.connected {
+            println!("[DISCORD] Reconnected, restoring presence");
+        }
     }
 
-    pub fn update_presence(
-        &mut self,
-        details: &str,
-        state: &str,
-        large_image: &str,
-        small_text: Option<&str>,
-    ) {
-        if !self.ensure_connected() {
+    /// Shows the presence as soon as Discord's rate limit allows.
+    pub fn update_presence(&mut self, presence: &Presence) {
+        if !self.pending && self.presence.as_ref() == Some(presence) {
             return;
         }
 
-        self.reset_start_time();
+        self.presence = Some(presence.clone());
+        self.pending = true;
+        self.flush();
+    }
 
-        if let Some(client) = self.client.as_mut() {
-            let mut assets = activity::Assets::new()
-                .large_image(large_image)
-                .large_text(details);
+    /// Sends the pending presence, unless the rate limit is used up.
+    fn flush(&mut self) {
+        if !self.pending {
+            return;
+        }
 
-            if let Some(txt) = small_text {
-                assets = assets.small_image("roblox_logo").small_text(txt);
-            }
+        let (updates, window) = self.rate_limit;
+        while self.recent_updates.front().is_some_and(|sent| sent.elapsed() >= window) {
+            self.recent_updates.pop_front();
+        }
+        if self.recent_updates.len() >= updates {
+            return;
+        }
 
-            let timestamps = activity::Timestamps::new().start(self.start_time as i64);
+        if !self.ensure_connected() {
+            return;
+        }
 
-            let activity = activity::Activity::new()
-                .details(details)
-                .state(state)
-                .assets(assets)
-                .timestamps(timestamps);
+        let activity = self.presence.as_ref().map(|presence| presence.to_activity(&self.asset_keys));
+        self.pending = false;
+        self.recent_updates.push_back(Instant::now());
+        self.last_write = Instant::now();
+        if let Err(e) = self.transport.set_activity(activity.as_ref()) {
+            self.handle_error(e);
+        }
+    }
 
-            if let Err(e) = client.set_activity(activity) {
-                eprintln!("Failed to set activity (Discord might have closed): {}", e);
-                self.connected = false;
-                let _ = client.close();
-            }
+    /// Changes only the party of what's currently shown.
+    pub fn update_party(&mut self, party: Option<PartySize>) {
+        let Some(presence) = self.presence.as_ref() else {
+            return;
+        };
+
+        if presence.party != party {
+            let presence = presence.clone().party(party);
+            self.update_presence(&presence);
         }
     }
 
     pub fn clear_presence(&mut self) {
-        if self.connected {
-            if let Some(client) = self.client.as_mut() {
-                if let Err(_) = client.clear_activity() {
-                    self.connected = false;
-                    let _ = client.close();
-                }
-            }
-        }
+        self.presence = None;
+        // Discord forgets the activity by itself when we're not connected
+        self.pending = self.connected;
+        self.flush();
+    }
+
+    pub fn status(&self) -> &DiscordStatus {
+        &self.status
     }
 
     pub fn close(&mut self) {
-        if let Some(client) = self.client.as_mut() {
-            let _ = client.close();
-        }
+        self.transport.close();
         self.connected = false;
+        // The next connection starts with nothing shown
+        self.pending = self.presence.is_some();
+        self.recent_updates.clear();
+        if let DiscordStatus::Connected(_) = self.status {
+            self.status = DiscordStatus::Disconnected;
+        }
+    }
+}
+
+impl Default for DiscordClient {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
@@ -0,0 +1,404 @@
+//! Discord's local RPC protocol: JSON payloads behind an 8-byte header
+//! (opcode and length, both little-endian u32), sent over a Unix socket or a
+//! Windows named pipe.
+
+use serde_json::{json, Value};
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+const OPCODE_HANDSHAKE: u32 = 0;
+const OPCODE_FRAME: u32 = 1;
+const OPCODE_CLOSE: u32 = 2;
+const OPCODE_PING: u32 = 3;
+const OPCODE_PONG: u32 = 4;
+// Far more than any reply we expect; a bigger header means we lost the framing
+const MAX_PAYLOAD_LEN: u32 = 1 << 20;
+// Discord answers within milliseconds, so a long wait means it's hung
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Who Discord says is logged in, from the READY reply to the handshake.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReadyInfo {
+    pub user_id: String,
+    pub username: String,
+    /// "production", "canary" or "ptb" on release builds of Discord.
+    pub environment: Option<String>,
+}
+
+impl ReadyInfo {
+    fn parse(data: &Value) -> Option<Self> {
+        let user = &data["user"];
+
+        Some(ReadyInfo {
+            user_id: user["id"].as_str()?.to_string(),
+            username: user["username"].as_str()?.to_string(),
+            environment: data["config"]["environment"].as_str().map(str::to_string),
+        })
+    }
+}
+
+/// How presence updates reach Discord.
+pub trait PresenceTransport: Send {
+    /// Takes effect on the next connect.
+    fn set_client_id(&mut self, client_id: &str);
+    fn connect(&mut self) -> Result<ReadyInfo, IpcError>;
+    /// Shows the activity, or clears it when `None`.
+    fn set_activity(&mut self, activity: Option<&Value>) -> Result<(), IpcError>;
+    fn ping(&mut self) -> Result<(), IpcError>;
+    fn close(&mut self);
+}
+
+#[derive(Debug)]
+pub enum IpcError {
+    /// No Discord client is listening on any of the IPC sockets.
+    NotRunning,
+    Io(io::Error),
+    /// Discord closed the connection, e.g. because the application ID is unknown.
+    Closed { code: i64, message: String },
+    /// Discord answered a command with an ERROR event.
+    Rejected { code: i64, message: String },
+    InvalidResponse(String),
+}
+
+impl fmt::Display for IpcError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            IpcError::NotRunning => write!(f, "Discord is not running"),
+            IpcError::Io(e) => write!(f, "{}", e),
+            IpcError::Closed { code, message } => write!(f, "Discord closed the connection: {} ({})", message, code),
+            IpcError::Rejected { code, message } => write!(f, "Discord rejected the request: {} ({})", message, code),
+            IpcError::InvalidResponse(e) => write!(f, "Unexpected response from Discord: {}", e),
+        }
+    }
+}
+
+impl IpcError {
+    /// Discord doesn't know the application ID we connected with.
+    pub fn is_invalid_client_id(&self) -> bool {
+        matches!(self, IpcError::Closed { code: 4000, .. })
+    }
+}
+
+impl std::error::Error for IpcError {}
+
+impl From<io::Error> for IpcError {
+    fn from(e: io::Error) -> Self {
+        IpcError::Io(e)
+    }
+}
+
+trait Stream: Read + Write + Send {}
+
+impl<T: Read + Write + Send> Stream for T {}
+
+/// Talks to the Discord client running on this machine.
+pub struct IpcTransport {
+    client_id: String,
+    socket_path: Option<PathBuf>,
+    stream: Option<Box<dyn Stream>>,
+    nonce: u64,
+}
+
+impl IpcTransport {
+    pub fn new(client_id: &str) -> Self {
+        IpcTransport {
+            client_id: client_id.to_string(),
+            socket_path: None,
+            stream: None,
+            nonce: 0,
+        }
+    }
+
+    /// Connects to the given socket instead of looking for Discord's.
+    pub fn with_socket_path(client_id: &str, socket_path: PathBuf) -> Self {
+        let mut transport = Self::new(client_id);
+        transport.socket_path = Some(socket_path);
+        transport
+    }
+
+    fn open(&self) -> Result<Box<dyn Stream>, IpcError> {
+        let candidates = match &self.socket_path {
+            Some(path) => vec![path.clone()],
+            None => socket_paths(),
+        };
+
+        candidates
+            .iter()
+            .find_map(|path| open_socket(path).ok())
+            .ok_or(IpcError::NotRunning)
+    }
+
+    fn send(&mut self, opcode: u32, payload: &Value) -> Result<(), IpcError> {
+        let stream = self.stream.as_mut().ok_or(IpcError::NotRunning)?;
+        let body = payload.to_string();
+
+        let mut frame = Vec::with_capacity(8 + body.len());
+        frame.extend_from_slice(&opcode.to_le_bytes());
+        frame.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        frame.extend_from_slice(body.as_bytes());
+
+        stream.write_all(&frame)?;
+        stream.flush()?;
+        Ok(())
+    }
+
+    fn recv(&mut self) -> Result<(u32, Value), IpcError> {
+        let stream = self.stream.as_mut().ok_or(IpcError::NotRunning)?;
+
+        let mut header = [0u8; 8];
+        stream.read_exact(&mut header)?;
+        let opcode = u32::from_le_bytes([header[0], header[1], header[2], header[3]]);
+        let len = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+
+        if len > MAX_PAYLOAD_LEN {
+            return Err(IpcError::InvalidResponse(format!("{} byte payload", len)));
+        }
+
+        let mut body = vec![0u8; len as usize];
+        stream.read_exact(&mut body)?;
+        let payload = serde_json::from_slice(&body).map_err(|e| IpcError::InvalidResponse(e.to_string()))?;
+
+        if opcode == OPCODE_CLOSE {
+            return Err(closed_error(&payload));
+        }
+
+        Ok((opcode, payload))
+    }
+
+    /// Sends a command and waits for the reply carrying the same nonce.
+    fn command(&mut self, cmd: &str, args: Value) -> Result<Value, IpcError> {
+        self.nonce += 1;
+        let nonce = format!("{}-{}", std::process::id(), self.nonce);
+
+        self.send(OPCODE_FRAME, &json!({ "cmd": cmd, "args": args, "nonce": nonce }))?;
+
+        loop {
+            let (opcode, payload) = self.recv()?;
+            if opcode != OPCODE_FRAME || payload["nonce"] != nonce.as_str() {
+                continue;
+            }
+
+            if payload["evt"] == "ERROR" {
+                return Err(rejected_error(&payload["data"]));
+            }
+            return Ok(payload);
+        }
+    }
+}
+
+impl PresenceTransport for IpcTransport {
+    fn set_client_id(&mut self, client_id: &str) {
+        self.client_id = client_id.to_string();
+    }
+
+    fn connect(&mut self) -> Result<ReadyInfo, IpcError> {
+        self.close();
+        self.stream = Some(self.open()?);
+
+        let handshake = json!({ "v": 1, "client_id": self.client_id });
+        let result = self.send(OPCODE_HANDSHAKE, &handshake).and_then(|_| self.recv());
+
+        let error = match result {
+            Ok((OPCODE_FRAME, payload)) if payload["evt"] == "READY" => match ReadyInfo::parse(&payload["data"]) {
+                Some(ready) => return Ok(ready),
+                None => IpcError::InvalidResponse(payload.to_string()),
+            },
+            Ok((_, payload)) if payload["evt"] == "ERROR" => rejected_error(&payload["data"]),
+            Ok((_, payload)) => IpcError::InvalidResponse(payload.to_string()),
+            Err(e) => e,
+        };
+
+        self.stream = None;
+        Err(error)
+    }
+
+    fn set_activity(&mut self, activity: Option<&Value>) -> Result<(), IpcError> {
+        let args = json!({ "pid": std::process::id(), "activity": activity });
+        self.command("SET_ACTIVITY", args).map(|_| ())
+    }
+
+    fn ping(&mut self) -> Result<(), IpcError> {
+        self.nonce += 1;
+        self.send(OPCODE_PING, &json!({ "nonce": self.nonce }))?;
+
+        loop {
+            if let (OPCODE_PONG, _) = self.recv()? {
+                return Ok(());
+            }
+        }
+    }
+
+    fn close(&mut self) {
+        if self.stream.is_some() {
+            let _ = self.send(OPCODE_CLOSE, &json!({}));
+            self.stream = None;
+        }
+    }
+}
+
+impl Drop for IpcTransport {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+fn closed_error(payload: &Value) -> IpcError {
+    IpcError::Closed {
+        code: payload["code"].as_i64().unwrap_or_default(),
+        message: payload["message"].as_str().unwrap_or_default().to_string(),
+    }
+}
+
+fn rejected_error(data: &Value) -> IpcError {
+    IpcError::Rejected {
+        code: data["code"].as_i64().unwrap_or_default(),
+        message: data["message"].as_str().unwrap_or_default().to_string(),
+    }
+}
+
+#[cfg(unix)]
+fn socket_paths() -> Vec<PathBuf> {
+    let base = ["XDG_RUNTIME_DIR", "TMPDIR", "TMP", "TEMP"]
+        .iter()
+        .find_map(std::env::var_os)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("/tmp"));
+
+    // Flatpak and Snap builds of Discord keep their socket in a subdirectory
+    let dirs = [
+        base.clone(),
+        base.join("app/com.discordapp.Discord"),
+        base.join("snap.discord"),
+    ];
+
+    dirs.iter()
+        .flat_map(|dir| (0..10).map(move |i| dir.join(format!("discord-ipc-{}", i))))
+        .collect()
+}
+
+#[cfg(windows)]
+fn socket_paths() -> Vec<PathBuf> {
+    (0..10)
+        .map(|i| PathBuf::from(format!(r"\\?\pipe\discord-ipc-{}", i)))
+        .collect()
+}
+
+#[cfg(unix)]
+fn open_socket(path: &Path) -> io::Result<Box<dyn Stream>> {
+    use std::os::unix::net::UnixStream;
+
+    let stream = UnixStream::connect(path)?;
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    Ok(Box::new(stream))
+}
+
+#[cfg(windows)]
+fn open_socket(path: &Path) -> io::Result<Box<dyn Stream>> {
+    Ok(Box::new(pipe::OverlappedPipe::open(path, READ_TIMEOUT)?))
+}
+
+/// Pipe handles have no read timeout, so every read and write is issued
+/// overlapped and abandoned if Discord doesn't finish it in time.
+#[cfg(windows)]
+mod pipe {
+    use std::io::{self, Read, Write};
+    use std::path::Path;
+    use std::time::Duration;
+
+    use windows::core::{Result as WinResult, HSTRING};
+    use windows::Win32::Foundation::{CloseHandle, ERROR_IO_PENDING, GENERIC_READ, GENERIC_WRITE, HANDLE, WAIT_OBJECT_0};
+    use windows::Win32::Storage::FileSystem::{CreateFileW, ReadFile, WriteFile, FILE_FLAG_OVERLAPPED, FILE_SHARE_NONE, OPEN_EXISTING};
+    use windows::Win32::System::Threading::{CreateEventW, WaitForSingleObject};
+    use windows::Win32::System::IO::{CancelIoEx, GetOverlappedResult, OVERLAPPED};
+
+    pub struct OverlappedPipe {
+        handle: HANDLE,
+        event: HANDLE,
+        timeout_ms: u32,
+    }
+
+    // The handles are only used through `&mut self`, one operation at a time
+    unsafe impl Send for OverlappedPipe {}
+
+    impl OverlappedPipe {
+        pub fn open(path: &Path, timeout: Duration) -> io::Result<Self> {
+            let timeout_ms = u32::try_from(timeout.as_millis()).unwrap_or(u32::MAX);
+
+            unsafe {
+                let handle = CreateFileW(
+                    &HSTRING::from(path),
+                    (GENERIC_READ | GENERIC_WRITE).0,
+                    FILE_SHARE_NONE,
+                    None,
+                    OPEN_EXISTING,
+                    FILE_FLAG_OVERLAPPED,
+                    None,
+                )?;
+                let event = match CreateEventW(None, true, false, None) {
+                    Ok(event) => event,
+                    Err(e) => {
+                        let _ = CloseHandle(handle);
+                        return Err(e.into());
+                    }
+                };
+
+                Ok(OverlappedPipe { handle, event, timeout_ms })
+            }
+        }
+
+        /// Runs one overlapped operation and waits for it to finish.
+        fn complete(&mut self, start: impl FnOnce(HANDLE, *mut OVERLAPPED) -> WinResult<()>) -> io::Result<usize> {
+            let mut overlapped = OVERLAPPED {
+                hEvent: self.event,
+                ..Default::default()
+            };
+
+            if let Err(e) = start(self.handle, &mut overlapped) {
+                if e.code() != ERROR_IO_PENDING.to_hresult() {
+                    return Err(e.into());
+                }
+            }
+
+            let mut transferred = 0u32;
+            unsafe {
+                if WaitForSingleObject(self.event, self.timeout_ms) != WAIT_OBJECT_0 {
+                    // The buffer and `overlapped` must outlive the operation, so
+                    // wait for the cancellation to land before giving up
+                    let _ = CancelIoEx(self.handle, Some(&overlapped));
+                    let _ = GetOverlappedResult(self.handle, &overlapped, &mut transferred, true);
+                    return Err(io::Error::new(io::ErrorKind::TimedOut, "Discord stopped responding"));
+                }
+
+                GetOverlappedResult(self.handle, &overlapped, &mut transferred, false)?;
+            }
+            Ok(transferred as usize)
+        }
+    }
+
+    impl Read for OverlappedPipe {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.complete(|handle, overlapped| unsafe { ReadFile(handle, Some(buf), None, Some(overlapped)) })
+        }
+    }
+
+    impl Write for OverlappedPipe {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.complete(|handle, overlapped| unsafe { WriteFile(handle, Some(buf), None, Some(overlapped)) })
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Drop for OverlappedPipe {
+        fn drop(&mut self) {
+            unsafe {
+                let _ = CloseHandle(self.handle);
+                let _ = CloseHandle(self.event);
+            }
+        }
+    }
+}
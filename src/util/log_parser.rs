@@ -1,12 +1,18 @@
-use regex::Regex;
+use std::collections::HashMap;
 use std::fs::{self, File, OpenOptions};
-use std::io::Read;
-use std::path::PathBuf;
-use std::time::SystemTime;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use notify::{Config, RecommendedWatcher, RecursiveMode, Watcher};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
 
 #[cfg(target_os = "windows")]
 use std::os::windows::fs::OpenOptionsExt;
 
+#[cfg(unix)]
+use std::os::unix::fs::MetadataExt;
+
 #[cfg(target_os = "windows")]
 const FILE_SHARE_READ: u32 = 1;
 #[cfg(target_os = "windows")]
@@ -14,145 +20,557 @@ const FILE_SHARE_WRITE: u32 = 2;
 #[cfg(target_os = "windows")]
 const FILE_SHARE_DELETE: u32 = 4;
 
+use super::log_patterns;
 use super::notifier;
-use super::paths;
+use super::paths::{self, RobloxType};
+use super::roblox_api::{GameId, PlaceId};
+use super::watcher::RobloxProcess;
+
+/// Identifies the file behind a path, so a replaced file can be told apart
+/// from the one we have open.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct FileIdentity(u64, u64);
+
+impl FileIdentity {
+    // Creation times can't tell files apart, as NTFS hands a file recreated
+    // under the same name the old one's creation time
+    #[cfg(target_os = "windows")]
+    fn of(file: &File) -> Option<Self> {
+        use std::os::windows::io::AsRawHandle;
+        use windows::Win32::Foundation::HANDLE;
+        use windows::Win32::Storage::FileSystem::{GetFileInformationByHandle, BY_HANDLE_FILE_INFORMATION};
+
+        let mut info = BY_HANDLE_FILE_INFORMATION::default();
+        unsafe { GetFileInformationByHandle(HANDLE(file.as_raw_handle()), &mut info) }.ok()?;
+
+        let index = (u64::from(info.nFileIndexHigh) << 32) | u64::from(info.nFileIndexLow);
+        Some(FileIdentity(u64::from(info.dwVolumeSerialNumber), index))
+    }
+
+    #[cfg(unix)]
+    fn of(file: &File) -> Option<Self> {
+        let metadata = file.metadata().ok()?;
+        Some(FileIdentity(metadata.dev(), metadata.ino()))
+    }
+
+    #[cfg(not(any(target_os = "windows", unix)))]
+    fn of(_file: &File) -> Option<Self> {
+        None
+    }
+}
 
 pub struct LogReader {
+    path: PathBuf,
     file: File,
-    buffer: String,
+    identity: Option<FileIdentity>,
+    offset: u64,
+    // Raw bytes of the trailing partial line, decoded once it's complete
+    buffer: Vec<u8>,
 }
 
 impl LogReader {
-    pub fn new(log_path: &PathBuf) -> Result<Self, std::io::Error> {
-        let mut opts = OpenOptions::new();
-        opts.read(true);
-
-        #[cfg(target_os = "windows")]
-        opts.share_mode(FILE_SHARE_READ | FILE_SHARE_WRITE | FILE_SHARE_DELETE);
-
-        let file = opts.open(log_path)?;
+    pub fn new(log_path: &Path) -> Result<Self, std::io::Error> {
+        let file = open_shared(log_path)?;
+        let identity = FileIdentity::of(&file);
 
         Ok(LogReader {
+            path: log_path.to_path_buf(),
             file,
-            buffer: String::new(),
+            identity,
+            offset: 0,
+            buffer: Vec::new(),
         })
     }
 
     pub fn get_new_lines(&mut self) -> Vec<String> {
-        let mut found_lines = Vec::new();
-        let mut chunk = Vec::new();
-
-        if let Ok(_) = self.file.read_to_end(&mut chunk) {
-            if !chunk.is_empty() {
-                let chunk_str = String::from_utf8_lossy(&chunk);
-                self.buffer.push_str(&chunk_str);
-            }
+        if let Err(e) = self.handle_rotation() {
+            eprintln!("[LOGS] Failed to reopen {:?}: {}", self.path.file_name().unwrap_or_default(), e);
+            return Vec::new();
         }
 
-        while let Some(newline_idx) = self.buffer.find('\n') {
-            let line: String = self.buffer.drain(..=newline_idx).collect();
-            found_lines.push(line.trim().to_string());
+        let mut chunk = Vec::new();
+        if self.file.seek(SeekFrom::Start(self.offset)).is_ok() && self.file.read_to_end(&mut chunk).is_ok() {
+            self.offset += chunk.len() as u64;
+            self.buffer.extend_from_slice(&chunk);
         }
 
+        // Everything up to the last newline is complete lines
+        let Some(end) = self.buffer.iter().rposition(|&b| b == b'\n') else {
+            return Vec::new();
+        };
+
+        let found_lines = self.buffer[..end]
+            .split(|&b| b == b'\n')
+            .map(|line| String::from_utf8_lossy(line).trim().to_string())
+            .collect();
+        self.buffer.drain(..=end);
+
         found_lines
     }
+
+    /// The last line, if the file ended without a newline. Only call this once
+    /// the file is known to be complete, as a live log may still finish it.
+    pub fn take_partial_line(&mut self) -> Option<String> {
+        let line = String::from_utf8_lossy(&std::mem::take(&mut self.buffer)).trim().to_string();
+        (!line.is_empty()).then_some(line)
+    }
+
+    /// Starts over if the file was replaced or truncated since the last read.
+    fn handle_rotation(&mut self) -> Result<(), std::io::Error> {
+        let file = open_shared(&self.path)?;
+        let identity = FileIdentity::of(&file);
+
+        if identity != self.identity {
+            println!("[LOGS] Log file was replaced, reopening");
+            self.file = file;
+            self.identity = identity;
+            self.reset();
+        } else if file.metadata()?.len() < self.offset {
+            println!("[LOGS] Log file was truncated, reading from the start");
+            self.reset();
+        }
+
+        Ok(())
+    }
+
+    fn reset(&mut self) {
+        self.offset = 0;
+        self.buffer.clear();
+    }
+}
+
+fn open_shared(path: &Path) -> Result<File, std::io::Error> {
+    let mut opts = OpenOptions::new();
+    opts.read(true);
+
+    #[cfg(target_os = "windows")]
+    opts.share_mode(FILE_SHARE_READ | FILE_SHARE_WRITE | FILE_SHARE_DELETE);
+
+    opts.open(path)
+}
+
+/// An IP and port pair as printed by the client.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerAddress {
+    pub ip: String,
+    pub port: u16,
+}
+
+/// How the client got into the server it is joining.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum ServerKind {
+    #[default]
+    Public,
+    Private,
+    Reserved,
+    Party,
+}
+
+/// How a Studio test session was started.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StudioTestMode {
+    /// Play Solo, with the developer's own character.
+    PlaySolo,
+    /// Run mode, simulating the server without a character.
+    Run,
+    /// A local test server with simulated clients.
+    TestServer,
+}
+
+impl StudioTestMode {
+    pub fn label(self) -> &'static str {
+        match self {
+            StudioTestMode::PlaySolo => "Play Solo",
+            StudioTestMode::Run => "Run mode",
+            StudioTestMode::TestServer => "Local server test",
+        }
+    }
 }
 
-pub fn get_place_id_from_line(line: &str) -> Option<String> {
-    let patterns = [
-        r"Launching experience at (\d+)",
-        r"! Joining game .* place (\d+)",
-        r"Joining game .* place (\d+)",
-        r"placeid:(\d+)",
-        r"placeId:(\d+)",
-        r"PlaceId=(\d+)",
-        r"universeId:(\d+)",
-    ];
+/// Everything the log reveals about the server the client is connected to.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ServerInfo {
+    pub job_id: Option<String>,
+    pub address: Option<ServerAddress>,
+    pub udmux: Option<ServerAddress>,
+    pub kind: ServerKind,
+    pub access_code: Option<String>,
+    /// Players in this server, when the log reports it.
+    pub player_count: Option<u32>,
+    pub max_players: Option<u32>,
+    // The kind is logged before the join it applies to
+    pending_kind: Option<ServerKind>,
+    pending_access_code: Option<String>,
+}
 
-    for pattern in &patterns {
-        if let Ok(re) = Regex::new(pattern) {
-            if let Some(caps) = re.captures(line) {
-                if let Some(id) = caps.get(1) {
-                    return Some(id.as_str().to_string());
+impl ServerInfo {
+    /// Fills in whatever the event says about the current server.
+    pub fn apply(&mut self, event: &LogEvent) {
+        match event {
+            LogEvent::JoinStarted { job_id, .. } => {
+                *self = ServerInfo {
+                    job_id: job_id.clone(),
+                    kind: self.pending_kind.take().unwrap_or_default(),
+                    access_code: self.pending_access_code.take(),
+                    ..ServerInfo::default()
+                };
+            }
+            LogEvent::ServerKindDetected { kind, access_code } => {
+                self.pending_kind = Some(*kind);
+                if access_code.is_some() {
+                    self.pending_access_code = access_code.clone();
+                }
+            }
+            LogEvent::ServerEndpoints { udmux, rcc } => {
+                self.udmux = Some(udmux.clone());
+                self.address = Some(rcc.clone());
+            }
+            LogEvent::JoinedServer { address: Some(address) } => {
+                self.address = Some(address.clone());
+            }
+            LogEvent::PlayerCount { players, max_players } => {
+                self.player_count = Some(*players);
+                if max_players.is_some() {
+                    self.max_players = *max_players;
                 }
             }
+            LogEvent::Teleport { kind } => {
+                // Anything detected earlier was for a join that never happened
+                self.pending_kind = Some(*kind);
+                self.pending_access_code = None;
+            }
+            // A teleport disconnects between its marker and the join it applies to
+            LogEvent::Disconnected => {
+                *self = ServerInfo {
+                    pending_kind: self.pending_kind.take(),
+                    pending_access_code: self.pending_access_code.take(),
+                    ..ServerInfo::default()
+                };
+            }
+            LogEvent::LeftGame | LogEvent::ReturnedToHome => {
+                *self = ServerInfo::default();
+            }
+            _ => {}
         }
     }
 
-    None
+    /// A link that launches Roblox straight into this server. Only public
+    /// servers can be joined by job ID, so other kinds have no link.
+    pub fn join_link(&self, place_id: PlaceId) -> Option<String> {
+        if self.kind != ServerKind::Public {
+            return None;
+        }
+
+        let job_id = self.job_id.as_ref()?;
+        Some(format!(
+            "https://www.roblox.com/games/start?placeId={}&gameInstanceId={}",
+            place_id, job_id
+        ))
+    }
+}
+
+/// A state transition recognised in a Roblox log.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LogEvent {
+    /// The client started joining a place.
+    JoinStarted {
+        place_id: PlaceId,
+        job_id: Option<String>,
+    },
+    /// The client was routed to a game server through a UDMUX proxy.
+    ServerEndpoints {
+        udmux: ServerAddress,
+        rcc: ServerAddress,
+    },
+    /// The upcoming join targets a non-public server.
+    ServerKindDetected {
+        kind: ServerKind,
+        access_code: Option<String>,
+    },
+    /// The client finished connecting to a game server.
+    JoinedServer { address: Option<ServerAddress> },
+    /// The server reported how many players it has.
+    PlayerCount {
+        players: u32,
+        max_players: Option<u32>,
+    },
+    /// The client started a teleport to another place or server.
+    Teleport { kind: ServerKind },
+    /// The connection to the game server was torn down.
+    Disconnected,
+    /// The player left the game from the in-game menu.
+    LeftGame,
+    /// The app navigated back to its home page.
+    ReturnedToHome,
+    /// Studio opened a place.
+    StudioPlaceOpened { game_id: GameId },
+    /// Studio opened a place file from disk.
+    StudioLocalFileOpened { file_name: String },
+    /// Studio closed the open place and went back to its start page.
+    StudioPlaceClosed,
+    /// Studio joined a Team Create session for the open place.
+    TeamCreateJoined,
+    /// Studio started testing the open place.
+    StudioTestStarted { mode: StudioTestMode },
+    /// Studio stopped testing and went back to editing.
+    StudioTestStopped,
+}
+
+pub fn parse_line(line: &str) -> Option<LogEvent> {
+    log_patterns::registry().parse_line(line)
+}
+
+/// Splits off the `2024-01-01T12:00:00.123Z` timestamp that starts every log
+/// line, returning it along with its time since the Unix epoch.
+pub fn line_timestamp(line: &str) -> Option<(&str, Duration)> {
+    let text = line.split(',').next()?;
+    let (date, time) = text.strip_suffix('Z')?.split_once('T')?;
+
+    let mut date_parts = date.splitn(3, '-').map(|p| p.parse::<i64>().ok());
+    let (year, month, day) = (date_parts.next()??, date_parts.next()??, date_parts.next()??);
+
+    let mut time_parts = time.splitn(3, ':');
+    let hours: u64 = time_parts.next()?.parse().ok()?;
+    let minutes: u64 = time_parts.next()?.parse().ok()?;
+    let seconds: f64 = time_parts.next()?.parse().ok()?;
+
+    let in_range = (1..=12).contains(&month)
+        && (1..=31).contains(&day)
+        && hours < 24
+        && minutes < 60
+        && (0.0..60.0).contains(&seconds);
+    if !in_range {
+        return None;
+    }
+
+    // Days since the epoch for a proleptic Gregorian date
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let year_of_era = y - era * 400;
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = u64::try_from(era.checked_mul(146097)?.checked_add(day_of_era - 719468)?).ok()?;
+
+    let whole_seconds = days.checked_mul(86400)?.checked_add(hours * 3600 + minutes * 60)?;
+    let timestamp = Duration::from_secs(whole_seconds).checked_add(Duration::from_secs_f64(seconds))?;
+    Some((text, timestamp))
+}
+
+/// A log file being followed for one Roblox process.
+struct FollowedLog {
+    path: PathBuf,
+    reader: LogReader,
+    caught_up: bool,
+}
+
+/// New events from the log of one Roblox process.
+pub struct LogBatch {
+    pub process: RobloxProcess,
+    /// The events are the log's backlog from before we started following it.
+    pub catching_up: bool,
+    pub events: Vec<LogEvent>,
 }
 
+/// A log file in the logs directory, as far as we can tell without reading it.
+struct LogFile {
+    path: PathBuf,
+    roblox_type: Option<RobloxType>,
+    created: u64,
+}
+
+// A process writes its log shortly after starting; allow for clock granularity
+const LOG_START_SLACK_SECS: u64 = 5;
+
+#[derive(Default)]
 pub struct LogMonitor {
-    reader: Option<(PathBuf, LogReader)>,
+    processes: Vec<RobloxProcess>,
+    logs: HashMap<u32, FollowedLog>,
+    watcher: Option<RecommendedWatcher>,
 }
 
 impl LogMonitor {
     pub fn new() -> Self {
-        LogMonitor { reader: None }
+        Self::default()
     }
 
-    pub fn check_latest_log(&mut self) -> Option<String> {
-        let latest_path = get_latest_log_path()?;
+    /// Starts watching the logs directory. The receiver gets the path of every
+    /// log file that is created or written to; it closes straight away if the
+    /// directory can't be watched, in which case callers should keep polling.
+    pub fn watch_logs(&mut self) -> UnboundedReceiver<PathBuf> {
+        let (tx, rx) = unbounded_channel();
 
-        let needs_new_reader = match &self.reader {
-            Some((path, _)) => path != &latest_path,
-            None => true,
-        };
+        self.watcher = paths::roblox_logs().and_then(|logs_dir| {
+            let mut watcher = RecommendedWatcher::new(move |res: Result<notify::Event, _>| {
+                if let Ok(event) = res {
+                    if matches!(event.kind, notify::EventKind::Create(_) | notify::EventKind::Modify(_)) {
+                        for path in event.paths.into_iter().filter(|p| is_log_file(p)) {
+                            let _ = tx.send(path);
+                        }
+                    }
+                }
+            }, Config::default()).ok()?;
+
+            if let Err(e) = watcher.watch(&logs_dir, RecursiveMode::NonRecursive) {
+                eprintln!("[LOGS] Failed to watch {:?}, falling back to polling: {}", logs_dir, e);
+                return None;
+            }
+
+            Some(watcher)
+        });
+
+        rx
+    }
+
+    /// Follows logs for the given processes and drops those of exited ones.
+    pub fn set_processes(&mut self, processes: Vec<RobloxProcess>) {
+        self.logs
+            .retain(|pid, _| processes.iter().any(|p| p.pid == *pid));
+        self.processes = processes;
+        self.rescan();
+    }
+
+    /// Notes a log file the watcher saw change.
+    pub fn log_changed(&mut self, path: PathBuf) {
+        if !self.logs.values().any(|log| log.path == path) {
+            self.rescan();
+        }
+    }
+
+    /// Lists the logs directory and starts following the log of every
+    /// process that doesn't have one yet.
+    pub fn rescan(&mut self) {
+        if self.processes.iter().all(|p| self.logs.contains_key(&p.pid)) {
+            return;
+        }
+
+        let log_files = list_log_files();
+
+        for process in &self.processes {
+            if self.logs.contains_key(&process.pid) {
+                continue;
+            }
+
+            // Processes are oldest first, so each takes the earliest log
+            // created after it started that no older process claimed
+            let log_file = log_files
+                .iter()
+                .filter(|log| !self.logs.values().any(|followed| followed.path == log.path))
+                .filter(|log| log.roblox_type.is_none_or(|t| t == process.roblox_type))
+                .filter(|log| log.created + LOG_START_SLACK_SECS >= process.start_time)
+                .min_by_key(|log| log.created);
+
+            let Some(log_file) = log_file else {
+                continue;
+            };
 
-        if needs_new_reader {
             println!(
-                "[LOGS] Switching to log file: {:?}",
-                latest_path.file_name().unwrap()
+                "[LOGS] Following {:?} for {:?} process {}",
+                log_file.path.file_name().unwrap_or_default(),
+                process.roblox_type,
+                process.pid
             );
-            match LogReader::new(&latest_path) {
+
+            match LogReader::new(&log_file.path) {
                 Ok(reader) => {
-                    self.reader = Some((latest_path.clone(), reader));
+                    self.logs.insert(
+                        process.pid,
+                        FollowedLog {
+                            path: log_file.path.clone(),
+                            reader,
+                            caught_up: false,
+                        },
+                    );
                 }
                 Err(e) => {
                     notifier::error("Log Error", &format!("Failed to open log file: {}", e));
-                    return None;
                 }
             }
         }
+    }
 
-        if let Some((_, reader)) = self.reader.as_mut() {
-            let lines = reader.get_new_lines();
-            for line in lines {
-                if let Some(id) = get_place_id_from_line(&line) {
-                    return Some(id);
-                }
+    /// Reads every followed log. A log's first batch is always returned, even
+    /// if empty, so callers know its backlog has been handled.
+    pub fn check_logs(&mut self) -> Vec<LogBatch> {
+        let mut batches = Vec::new();
+
+        for process in &self.processes {
+            let Some(log) = self.logs.get_mut(&process.pid) else {
+                continue;
+            };
+
+            let events: Vec<LogEvent> = log
+                .reader
+                .get_new_lines()
+                .iter()
+                .filter_map(|line| parse_line(line))
+                .collect();
+
+            let catching_up = !log.caught_up;
+            log.caught_up = true;
+
+            if catching_up || !events.is_empty() {
+                batches.push(LogBatch {
+                    process: *process,
+                    catching_up,
+                    events,
+                });
             }
         }
 
-        None
+        batches
     }
 
     pub fn clear(&mut self) {
-        self.reader = None;
+        self.logs.clear();
+        self.processes.clear();
     }
 }
 
-fn get_latest_log_path() -> Option<PathBuf> {
-    let logs_dir = paths::roblox_logs()?;
+fn list_log_files() -> Vec<LogFile> {
+    let Some(logs_dir) = paths::roblox_logs() else {
+        return Vec::new();
+    };
+
+    let Ok(entries) = fs::read_dir(logs_dir) else {
+        return Vec::new();
+    };
 
-    let mut entries: Vec<_> = fs::read_dir(logs_dir)
-        .ok()?
+    entries
         .filter_map(|entry| entry.ok())
-        .filter(|entry| {
-            entry
-                .path()
-                .extension()
-                .map(|e| e == "log")
-                .unwrap_or(false)
+        .map(|entry| entry.path())
+        .filter(|path| is_log_file(path))
+        .filter_map(|path| {
+            let metadata = fs::metadata(&path).ok()?;
+            let created = metadata
+                .created()
+                .or_else(|_| metadata.modified())
+                .unwrap_or(SystemTime::UNIX_EPOCH);
+
+            Some(LogFile {
+                roblox_type: log_file_type(&path),
+                created: created
+                    .duration_since(SystemTime::UNIX_EPOCH)
+                    .map(|d| d.as_secs())
+                    .unwrap_or(0),
+                path,
+            })
         })
-        .collect();
+        .collect()
+}
+
+/// Roblox names its logs like `0.600.0.6000123_20240101T000000Z_Player_1A2B3_last.log`.
+pub fn log_file_type(path: &Path) -> Option<RobloxType> {
+    let name = path.file_name()?.to_string_lossy();
 
-    entries.sort_by_key(|entry| {
-        entry
-            .metadata()
-            .and_then(|m| m.modified())
-            .unwrap_or(SystemTime::UNIX_EPOCH)
-    });
+    if name.contains("_Studio_") {
+        Some(RobloxType::Studio)
+    } else if name.contains("_Player_") {
+        Some(RobloxType::Player)
+    } else {
+        None
+    }
+}
 
-    entries.last().map(|e| e.path())
-}
\ No newline at end of file
+fn is_log_file(path: &Path) -> bool {
+    path.extension().map(|e| e == "log").unwrap_or(false)
+}
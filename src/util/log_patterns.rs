@@ -0,0 +1,248 @@
+use regex::{Captures, Regex, RegexSet};
+use serde::Deserialize;
+use std::fs;
+use std::sync::OnceLock;
+
+use super::log_parser::{LogEvent, ServerAddress, ServerKind, StudioTestMode};
+use super::notifier;
+use super::roblox_api::{GameId, PlaceId, UniverseId};
+use super::settings::Settings;
+
+/// The event a pattern produces. Values are read from named capture groups:
+///
+/// - `join_started`: `place_id`, optional `job_id`
+/// - `server_endpoints`: `udmux_ip`, `udmux_port`, `rcc_ip`, `rcc_port`
+/// - `joined_server`: optional `ip` and `port`
+/// - `private_server`, `reserved_server`, `party_server`: optional `access_code`
+/// - `teleport`: optional `reserved`, present when the target is a reserved server
+/// - `player_count`: `players`, optional `max_players`. There is no built-in
+///   pattern, since no known log line reports the server's player count.
+/// - `studio_place_opened`: `place_id` or `universe_id`
+/// - `studio_local_file_opened`: `file_name`, of which only the last path component is kept
+///
+/// Events without a built-in pattern, such as returning to the home page,
+/// opening a local file, closing a place, joining Team Create or starting a
+/// playtest, are left to `patterns.toml` until a real log line for them is known.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    JoinStarted,
+    ServerEndpoints,
+    JoinedServer,
+    PlayerCount,
+    PrivateServer,
+    ReservedServer,
+    PartyServer,
+    Teleport,
+    Disconnected,
+    LeftGame,
+    ReturnedToHome,
+    StudioPlaceOpened,
+    StudioLocalFileOpened,
+    StudioPlaceClosed,
+    TeamCreateJoined,
+    PlaySoloStarted,
+    RunStarted,
+    TestServerStarted,
+    StudioTestStopped,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct LogPattern {
+    pub event: EventKind,
+    pub regex: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct PatternsFile {
+    #[serde(default)]
+    patterns: Vec<LogPattern>,
+}
+
+// Earlier patterns win when several match the same line
+const BUILTIN_PATTERNS: &[(EventKind, &str)] = &[
+    (
+        EventKind::JoinStarted,
+        r"! Joining game '(?P<job_id>[0-9a-fA-F\-]+)' place (?P<place_id>\d+)",
+    ),
+    (EventKind::JoinStarted, r"Launching experience at (?P<place_id>\d+)"),
+    (EventKind::JoinStarted, r"Joining game .* place (?P<place_id>\d+)"),
+    (
+        EventKind::ServerEndpoints,
+        r"UDMUX Address = (?P<udmux_ip>[0-9\.]+), Port = (?P<udmux_port>\d+) \| RCC Server Address = (?P<rcc_ip>[0-9\.]+), Port = (?P<rcc_port>\d+)",
+    ),
+    (EventKind::JoinedServer, r"serverId: (?P<ip>[0-9\.]+)\|(?P<port>\d+)"),
+    (EventKind::JoinedServer, r"Report game_join_loadtime"),
+    (
+        EventKind::PrivateServer,
+        r#"joinGamePostPrivateServer(?:.*"accessCode":"(?P<access_code>[0-9a-fA-F\-]+)")?"#,
+    ),
+    (
+        EventKind::ReservedServer,
+        r#"joinGamePostReservedServer(?:.*"accessCode":"(?P<access_code>[0-9a-fA-F\-]+)")?"#,
+    ),
+    (
+        EventKind::PartyServer,
+        r#"joinGamePostParty(?:.*"accessCode":"(?P<access_code>[0-9a-fA-F\-]+)")?"#,
+    ),
+    (EventKind::Teleport, r"initiateTeleport(?P<reserved>ToReservedServer)?"),
+    (EventKind::Disconnected, r"Time to disconnect replication data"),
+    (EventKind::LeftGame, r"leaveUGCGameInternal"),
+    (EventKind::StudioPlaceOpened, r"placeid:(?P<place_id>\d+)"),
+    (EventKind::StudioPlaceOpened, r"placeId:(?P<place_id>\d+)"),
+    (EventKind::StudioPlaceOpened, r"PlaceId=(?P<place_id>\d+)"),
+    (EventKind::StudioPlaceOpened, r"universeId:(?P<universe_id>\d+)"),
+];
+
+pub struct PatternRegistry {
+    set: RegexSet,
+    patterns: Vec<(EventKind, Regex)>,
+}
+
+impl PatternRegistry {
+    pub fn new(patterns: &[LogPattern]) -> Result<Self, regex::Error> {
+        let set = RegexSet::new(patterns.iter().map(|p| &p.regex))?;
+        let patterns = patterns
+            .iter()
+            .map(|p| Ok((p.event, Regex::new(&p.regex)?)))
+            .collect::<Result<_, regex::Error>>()?;
+
+        Ok(PatternRegistry { set, patterns })
+    }
+
+    pub fn builtin() -> Self {
+        Self::new(&builtin_patterns()).expect("built-in log patterns are valid")
+    }
+
+    /// Built-in patterns, preceded by any from the user's patterns file.
+    pub fn load() -> Self {
+        let mut patterns = load_user_patterns();
+        if patterns.is_empty() {
+            return Self::builtin();
+        }
+
+        println!("[LOGS] Loaded {} custom log patterns", patterns.len());
+        patterns.extend(builtin_patterns());
+
+        match Self::new(&patterns) {
+            Ok(registry) => registry,
+            Err(e) => {
+                notifier::error("Log Patterns Error", &format!("Invalid custom log pattern: {}", e));
+                Self::builtin()
+            }
+        }
+    }
+
+    pub fn parse_line(&self, line: &str) -> Option<LogEvent> {
+        self.set.matches(line).iter().find_map(|index| {
+            let (kind, regex) = &self.patterns[index];
+            build_event(*kind, &regex.captures(line)?)
+        })
+    }
+}
+
+/// The registry used for all log parsing, compiled on first use.
+pub fn registry() -> &'static PatternRegistry {
+    static REGISTRY: OnceLock<PatternRegistry> = OnceLock::new();
+    REGISTRY.get_or_init(PatternRegistry::load)
+}
+
+pub fn builtin_patterns() -> Vec<LogPattern> {
+    BUILTIN_PATTERNS
+        .iter()
+        .map(|(event, regex)| LogPattern {
+            event: *event,
+            regex: regex.to_string(),
+        })
+        .collect()
+}
+
+fn load_user_patterns() -> Vec<LogPattern> {
+    let path = Settings::patterns_path();
+    if !path.exists() {
+        return Vec::new();
+    }
+
+    let parsed = fs::read_to_string(&path)
+        .map_err(|e| e.to_string())
+        .and_then(|content| toml::from_str::<PatternsFile>(&content).map_err(|e| e.to_string()));
+
+    match parsed {
+        Ok(file) => file.patterns,
+        Err(e) => {
+            notifier::error("Log Patterns Error", &format!("Failed to load {}: {}", path.display(), e));
+            Vec::new()
+        }
+    }
+}
+
+fn build_event(kind: EventKind, caps: &Captures) -> Option<LogEvent> {
+    let text = |name: &str| caps.name(name).map(|m| m.as_str().to_string());
+    let id = |name: &str| text(name)?.parse::<u64>().ok();
+    let address = |ip: &str, port: &str| {
+        Some(ServerAddress {
+            ip: text(ip)?,
+            port: text(port)?.parse().ok()?,
+        })
+    };
+    let server_kind = |kind: ServerKind| LogEvent::ServerKindDetected {
+        kind,
+        access_code: text("access_code"),
+    };
+
+    Some(match kind {
+        EventKind::JoinStarted => LogEvent::JoinStarted {
+            place_id: PlaceId(id("place_id")?),
+            job_id: text("job_id"),
+        },
+        EventKind::ServerEndpoints => LogEvent::ServerEndpoints {
+            udmux: address("udmux_ip", "udmux_port")?,
+            rcc: address("rcc_ip", "rcc_port")?,
+        },
+        EventKind::JoinedServer => LogEvent::JoinedServer {
+            address: address("ip", "port"),
+        },
+        EventKind::PlayerCount => LogEvent::PlayerCount {
+            players: text("players")?.parse().ok()?,
+            max_players: text("max_players").and_then(|max| max.parse().ok()),
+        },
+        EventKind::PrivateServer => server_kind(ServerKind::Private),
+        EventKind::ReservedServer => server_kind(ServerKind::Reserved),
+        EventKind::PartyServer => server_kind(ServerKind::Party),
+        EventKind::Teleport => LogEvent::Teleport {
+            kind: match caps.name("reserved") {
+                Some(_) => ServerKind::Reserved,
+                None => ServerKind::Public,
+            },
+        },
+        EventKind::Disconnected => LogEvent::Disconnected,
+        EventKind::LeftGame => LogEvent::LeftGame,
+        EventKind::ReturnedToHome => LogEvent::ReturnedToHome,
+        EventKind::StudioPlaceOpened => LogEvent::StudioPlaceOpened {
+            game_id: match (id("place_id"), id("universe_id")) {
+                (Some(place_id), _) => GameId::Place(PlaceId(place_id)),
+                (None, Some(universe_id)) => GameId::Universe(UniverseId(universe_id)),
+                (None, None) => return None,
+            },
+        },
+        EventKind::StudioLocalFileOpened => {
+            let path = text("file_name")?;
+            LogEvent::StudioLocalFileOpened {
+                // Folder names can be personal, so only the file name is kept
+                file_name: path.rsplit(['/', '\\']).next()?.to_string(),
+            }
+        }
+        EventKind::StudioPlaceClosed => LogEvent::StudioPlaceClosed,
+        EventKind::TeamCreateJoined => LogEvent::TeamCreateJoined,
+        EventKind::PlaySoloStarted => LogEvent::StudioTestStarted {
+            mode: StudioTestMode::PlaySolo,
+        },
+        EventKind::RunStarted => LogEvent::StudioTestStarted {
+            mode: StudioTestMode::Run,
+        },
+        EventKind::TestServerStarted => LogEvent::StudioTestStarted {
+            mode: StudioTestMode::TestServer,
+        },
+        EventKind::StudioTestStopped => LogEvent::StudioTestStopped,
+    })
+}
@@ -1,7 +1,13 @@
+pub mod clipboard;
 pub mod discord;
+pub mod discord_ipc;
 pub mod log_parser;
+pub mod log_patterns;
 pub mod paths;
+pub mod presence;
+pub mod replay;
 pub mod roblox_api;
+pub mod session;
 pub mod watcher;
 pub mod notifier;
 pub mod settings;
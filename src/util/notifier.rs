@@ -3,6 +3,15 @@ use notify_rust::Notification;
 pub fn error(title: &str, message: &str) {
     eprintln!("[ERROR] {}: {}", title, message);
 
+    let _ = Notification::new()
+    .summary(title)
+    .body(message)
+    .show();
+}
+
+pub fn info(title: &str, message: &str) {
+    println!("[INFO] {}: {}", title, message);
+
     let _ = Notification::new()
     .summary(title)
     .body(message)
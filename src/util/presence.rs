@@ -0,0 +1,101 @@
+use super::discord::{ActivityButton, PartySize, Presence};
+use super::paths::RobloxType;
+use super::roblox_api::GameDetails;
+use super::session::{Place, Session};
+
+/// What the activity shows for a session, or `None` if it needs the game
+/// details and they weren't loaded.
+pub fn session_presence(
+    roblox_type: RobloxType,
+    session: &Session,
+    details: Option<&GameDetails>,
+    sub_place: Option<&str>,
+    join_link: Option<String>,
+) -> Option<Presence> {
+    match &session.place {
+        Place::LocalFile(file_name) => Some(local_file_presence(file_name, session)),
+        _ if roblox_type == RobloxType::Player && session.is_private() => Some(private_server_presence(session)),
+        Place::Published(_) => Some(game_presence(roblox_type, details?, sub_place, session, join_link)),
+    }
+}
+
+pub fn game_presence(roblox_type: RobloxType, details: &GameDetails, sub_place: Option<&str>, session: &Session, join_link: Option<String>) -> Presence {
+    let mut buttons: Vec<ActivityButton> = details
+        .page_url()
+        .map(|url| ActivityButton::new("View Game", url))
+        .into_iter()
+        .collect();
+    if let Some(link) = join_link {
+        buttons.push(ActivityButton::new("Join Server", link));
+    }
+
+    match roblox_type {
+        RobloxType::Player => {
+            let state_str = match sub_place {
+                Some(place) => format!("In {}", place),
+                None => format!("by {}", details.creator_name),
+            };
+
+            Presence::new(&details.name, &state_str, &details.thumbnail_url)
+                .start_time(session.started_at)
+                .party(party_size(session, details))
+                .buttons(buttons)
+        }
+        RobloxType::Studio => {
+            let mut state_str = format!("{} {}", studio_activity(session), sub_place.unwrap_or(&details.name));
+            if session.team_create {
+                state_str.push_str(" in Team Create");
+            }
+
+            Presence::new(&details.name, &state_str, &details.thumbnail_url)
+                .start_time(session.activity_started_at())
+                .small_text(studio_small_text(session))
+                .buttons(buttons)
+        }
+    }
+}
+
+/// Doesn't leak which game a private server belongs to.
+pub fn private_server_presence(session: &Session) -> Presence {
+    Presence::new("Roblox", "In a private server", "roblox_logo").start_time(session.started_at)
+}
+
+pub fn local_file_presence(file_name: &str, session: &Session) -> Presence {
+    let state_str = format!("{} a local file", studio_activity(session));
+
+    Presence::new(file_name, &state_str, "roblox_studio")
+        .start_time(session.activity_started_at())
+        .small_text(studio_small_text(session))
+}
+
+/// Shown while Roblox is open but not in a game.
+pub fn idle_presence(roblox_type: RobloxType, started_at: u64) -> Presence {
+    let idle = match roblox_type {
+        RobloxType::Player => Presence::new("Roblox", "In menus", "roblox_logo"),
+        RobloxType::Studio => Presence::new("Roblox Studio", "Developing", "roblox_studio"),
+    };
+    idle.start_time(started_at)
+}
+
+/// Players in the user's own server, which only the log can tell us.
+pub fn party_size(session: &Session, details: &GameDetails) -> Option<PartySize> {
+    let current = session.server.player_count?;
+    let max = session
+        .server
+        .max_players
+        .or_else(|| u32::try_from(details.max_players).ok())?;
+
+    (current > 0 && current <= max).then_some(PartySize { current, max })
+}
+
+fn studio_activity(session: &Session) -> &'static str {
+    if session.test.is_some() {
+        "Testing"
+    } else {
+        "Editing"
+    }
+}
+
+fn studio_small_text(session: &Session) -> &'static str {
+    session.test.map_or("Developing", |test| test.mode.label())
+}
@@ -0,0 +1,192 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use super::discord::Presence;
+use super::log_parser::{self, LogReader};
+use super::paths::RobloxType;
+use super::presence;
+use super::roblox_api::{GameDetails, GameId, PlaceId, UniverseId};
+use super::session::{unix_time, SessionChange, SessionTracker};
+
+const USAGE: &str = "Usage: roblox_discord_presence replay <logfile> [--realtime] [--speed <factor>] [--studio]";
+
+pub struct ReplayOptions {
+    pub log_path: PathBuf,
+    pub roblox_type: RobloxType,
+    /// Sleep between lines as long as the log says they were apart.
+    pub realtime: bool,
+    /// Divides the real-time delays, so 10 replays ten times faster.
+    pub speed: f64,
+}
+
+impl ReplayOptions {
+    pub fn from_args(args: &[String]) -> Result<Self, String> {
+        let mut log_path = None;
+        let mut roblox_type = None;
+        let mut realtime = false;
+        let mut speed = 1.0;
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--realtime" => realtime = true,
+                "--studio" => roblox_type = Some(RobloxType::Studio),
+                "--speed" => {
+                    speed = args
+                        .next()
+                        .and_then(|s| s.parse::<f64>().ok())
+                        .filter(|s| *s > 0.0)
+                        .ok_or_else(|| format!("--speed needs a positive number\n{}", USAGE))?;
+                    realtime = true;
+                }
+                _ if log_path.is_none() && !arg.starts_with("--") => log_path = Some(PathBuf::from(arg)),
+                _ => return Err(format!("Unexpected argument: {}\n{}", arg, USAGE)),
+            }
+        }
+
+        let log_path = log_path.ok_or_else(|| USAGE.to_string())?;
+        let roblox_type = roblox_type
+            .or_else(|| log_parser::log_file_type(&log_path))
+            .unwrap_or(RobloxType::Player);
+
+        Ok(ReplayOptions {
+            log_path,
+            roblox_type,
+            realtime,
+            speed,
+        })
+    }
+}
+
+/// Feeds a saved log through the parser and prints every event along with
+/// the session change it causes and what Discord would then show.
+pub fn run(options: &ReplayOptions) -> Result<(), String> {
+    let mut reader = LogReader::new(&options.log_path)
+        .map_err(|e| format!("Failed to open {}: {}", options.log_path.display(), e))?;
+    let mut tracker = SessionTracker::new(options.roblox_type);
+    let mut last_time: Option<Duration> = None;
+    let mut shown: Option<Presence> = None;
+
+    println!("Replaying {} as {:?}", options.log_path.display(), options.roblox_type);
+
+    let mut lines = reader.get_new_lines();
+    lines.extend(reader.take_partial_line());
+
+    for line in lines {
+        let Some(event) = log_parser::parse_line(&line) else {
+            continue;
+        };
+
+        let timestamp = log_parser::line_timestamp(&line);
+
+        if let (true, Some((_, time)), Some(last)) = (options.realtime, timestamp, last_time) {
+            std::thread::sleep(time.saturating_sub(last).div_f64(options.speed));
+        }
+        if let Some((_, time)) = timestamp {
+            last_time = Some(time);
+        }
+
+        let time_text = timestamp.map(|(text, _)| text).unwrap_or("-");
+        println!("{}  {:?}", time_text, event);
+
+        let Some(change) = tracker.apply(&event) else {
+            continue;
+        };
+
+        let indent = " ".repeat(time_text.len());
+        println!("{}  => {}", indent, describe(&change));
+
+        // The client skips updates that wouldn't change anything, and so do we
+        let presence = presence_after(options.roblox_type, &change).map(|presence| Presence {
+            start_time: None,
+            ..presence
+        });
+        if let Some(presence) = presence.filter(|presence| shown.as_ref() != Some(presence)) {
+            println!("{}  => Discord shows {}", indent, describe_presence(&presence));
+            shown = Some(presence);
+        }
+    }
+
+    Ok(())
+}
+
+fn presence_after(roblox_type: RobloxType, change: &SessionChange) -> Option<Presence> {
+    match change {
+        SessionChange::Started(session)
+        | SessionChange::Teleported(session)
+        | SessionChange::Updated(session)
+        | SessionChange::TestStarted(session)
+        | SessionChange::TestStopped(session) => {
+            let details = session.game_id().map(offline_details);
+            presence::session_presence(roblox_type, session, details.as_ref(), None, None)
+        }
+        SessionChange::Ended => Some(presence::idle_presence(roblox_type, unix_time())),
+    }
+}
+
+/// Game details aren't looked up during a replay, so games go by their ID.
+fn offline_details(game_id: GameId) -> GameDetails {
+    let (universe_id, root_place_id) = match game_id {
+        GameId::Place(place_id) => (UniverseId(0), place_id),
+        GameId::Universe(universe_id) => (universe_id, PlaceId(0)),
+    };
+
+    GameDetails {
+        universe_id,
+        root_place_id,
+        name: game_id.to_string(),
+        thumbnail_url: "roblox_logo".to_string(),
+        max_players: 0,
+        creator_name: "unknown creator".to_string(),
+    }
+}
+
+fn describe_presence(presence: &Presence) -> String {
+    let mut text = format!("\"{}\" / \"{}\"", presence.details, presence.state);
+
+    if let Some(small_text) = &presence.small_text {
+        text.push_str(&format!(" ({})", small_text));
+    }
+    if let Some(party) = presence.party {
+        text.push_str(&format!(", {} of {} players", party.current, party.max));
+    }
+    if !presence.buttons.is_empty() {
+        let labels: Vec<&str> = presence.buttons.iter().map(|button| button.label.as_str()).collect();
+        text.push_str(&format!(", buttons: {}", labels.join(", ")));
+    }
+
+    text
+}
+
+fn describe(change: &SessionChange) -> String {
+    match change {
+        SessionChange::Started(session) => format!(
+            "Session started in {} ({:?} server, job {})",
+            session.place,
+            session.server.kind,
+            session.server.job_id.as_deref().unwrap_or("unknown")
+        ),
+        SessionChange::Teleported(session) => format!(
+            "Teleported to {} ({:?} server, job {})",
+            session.place,
+            session.server.kind,
+            session.server.job_id.as_deref().unwrap_or("unknown")
+        ),
+        SessionChange::Updated(session) => match (&session.server.address, session.server.player_count) {
+            (Some(address), None) => format!("Server is {}:{}", address.ip, address.port),
+            (Some(address), Some(players)) => {
+                format!("Server is {}:{} with {} players", address.ip, address.port, players)
+            }
+            (None, Some(players)) => format!("Server has {} players", players),
+            (None, None) if session.team_create => "Joined Team Create".to_string(),
+            (None, None) => "Server details updated".to_string(),
+        },
+        SessionChange::TestStarted(session) => format!(
+            "Testing {} ({})",
+            session.place,
+            session.test.map(|test| test.mode.label()).unwrap_or("unknown mode")
+        ),
+        SessionChange::TestStopped(session) => format!("Test stopped, editing {}", session.place),
+        SessionChange::Ended => "Session ended, back in menus".to_string(),
+    }
+}
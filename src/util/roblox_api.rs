@@ -1,16 +1,53 @@
 use serde::Deserialize;
+use std::fmt;
+
+/// A single place, as seen in join and teleport lines.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PlaceId(pub u64);
+
+/// A game (experience), which groups one or more places.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct UniverseId(pub u64);
+
+/// Whichever ID a log line gave us for the current game.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum GameId {
+    Place(PlaceId),
+    Universe(UniverseId),
+}
+
+impl fmt::Display for PlaceId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl fmt::Display for UniverseId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl fmt::Display for GameId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GameId::Place(id) => write!(f, "place {}", id),
+            GameId::Universe(id) => write!(f, "universe {}", id),
+        }
+    }
+}
 
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct UniverseIdResponse {
-    pub universe_id: u64,
+    pub universe_id: UniverseId,
 }
 
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct GameInfo {
     pub name: String,
-    pub playing: u64,
+    pub root_place_id: PlaceId,
     pub max_players: u64,
     pub creator: CreatorInfo,
 }
@@ -38,22 +75,52 @@ pub struct ThumbnailsResponse {
     pub data: Vec<ThumbnailInfo>,
 }
 
-#[derive(Debug)]
+#[derive(Deserialize, Debug)]
+pub struct AssetDetailsResponse {
+    #[serde(rename = "Name")]
+    pub name: String,
+}
+
+#[derive(Debug, Clone)]
 pub struct GameDetails {
+    pub universe_id: UniverseId,
+    pub root_place_id: PlaceId,
     pub name: String,
     pub thumbnail_url: String,
-    pub playing: u64,
     pub max_players: u64,
     pub creator_name: String,
 }
 
-pub async fn get_game_details(place_id: &str) -> Result<GameDetails, reqwest::Error> {
+impl GameDetails {
+    /// The game's page on the Roblox website, unless the game couldn't be found.
+    pub fn page_url(&self) -> Option<String> {
+        (self.root_place_id != PlaceId(0)).then(|| format!("https://www.roblox.com/games/{}", self.root_place_id))
+    }
+}
+
+pub async fn get_game_details(game_id: GameId) -> Result<GameDetails, reqwest::Error> {
+    let universe_id = resolve_universe_id(game_id).await?;
+    get_universe_details(universe_id).await
+}
+
+pub async fn resolve_universe_id(game_id: GameId) -> Result<UniverseId, reqwest::Error> {
+    match game_id {
+        GameId::Place(place_id) => get_universe_id(place_id).await,
+        GameId::Universe(universe_id) => Ok(universe_id),
+    }
+}
+
+pub async fn get_universe_id(place_id: PlaceId) -> Result<UniverseId, reqwest::Error> {
     let client = reqwest::Client::new();
 
     let universe_url = format!("https://apis.roblox.com/universes/v1/places/{}/universe", place_id);
     let universe_res = client.get(&universe_url).send().await?;
     let universe_data: UniverseIdResponse = universe_res.json().await?;
-    let universe_id = universe_data.universe_id;
+    Ok(universe_data.universe_id)
+}
+
+pub async fn get_universe_details(universe_id: UniverseId) -> Result<GameDetails, reqwest::Error> {
+    let client = reqwest::Client::new();
 
     let game_url = format!("https://games.roblox.com/v1/games?universeIds={}", universe_id);
     let game_res = client.get(&game_url).send().await?;
@@ -61,9 +128,10 @@ pub async fn get_game_details(place_id: &str) -> Result<GameDetails, reqwest::Er
 
     if game_body.data.is_empty() {
         return Ok(GameDetails {
+            universe_id,
+            root_place_id: PlaceId(0),
             name: "Unknown Game".to_string(),
             thumbnail_url: "roblox_logo".to_string(),
-            playing: 0,
             max_players: 0,
             creator_name: "Unknown".to_string(),
         });
@@ -82,10 +150,20 @@ pub async fn get_game_details(place_id: &str) -> Result<GameDetails, reqwest::Er
     };
     
     Ok(GameDetails {
+        universe_id,
+        root_place_id: game_info.root_place_id,
         name: game_info.name.clone(),
         thumbnail_url,
-        playing: game_info.playing,
         max_players: game_info.max_players,
         creator_name: game_info.creator.name.clone(),
     })
-}
\ No newline at end of file
+}
+
+pub async fn get_place_name(place_id: PlaceId) -> Result<String, reqwest::Error> {
+    let client = reqwest::Client::new();
+
+    let asset_url = format!("https://economy.roblox.com/v2/assets/{}/details", place_id);
+    let asset_res = client.get(&asset_url).send().await?;
+    let asset_body: AssetDetailsResponse = asset_res.json().await?;
+    Ok(asset_body.name)
+}
@@ -0,0 +1,305 @@
+use std::fmt;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use super::log_parser::{LogEvent, ServerInfo, ServerKind, StudioTestMode};
+use super::paths::RobloxType;
+use super::roblox_api::{GameId, PlaceId};
+
+// A teleport disconnects from the old server within moments of starting, so
+// a later disconnect means the teleport failed and the player lost the server
+const TELEPORT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Where a session takes place.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Place {
+    /// A place published to Roblox.
+    Published(GameId),
+    /// A place file Studio opened from disk.
+    LocalFile(String),
+}
+
+impl fmt::Display for Place {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Place::Published(game_id) => write!(f, "{}", game_id),
+            Place::LocalFile(file_name) => write!(f, "local file {}", file_name),
+        }
+    }
+}
+
+/// One continuous stay in a place, started by a join event.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Session {
+    pub place: Place,
+    pub server: ServerInfo,
+    /// Studio is editing the place together with others.
+    pub team_create: bool,
+    /// Studio is testing the place rather than editing it.
+    pub test: Option<StudioTest>,
+    /// Unix time the session began, which is what Discord's timer counts from.
+    pub started_at: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StudioTest {
+    pub mode: StudioTestMode,
+    pub started_at: u64,
+}
+
+impl Session {
+    /// Start of whatever the session is doing right now.
+    pub fn activity_started_at(&self) -> u64 {
+        self.test.map_or(self.started_at, |test| test.started_at)
+    }
+
+    pub fn game_id(&self) -> Option<GameId> {
+        match self.place {
+            Place::Published(game_id) => Some(game_id),
+            Place::LocalFile(_) => None,
+        }
+    }
+
+    /// Private servers are invite-only, so which game they're in isn't shown.
+    pub fn is_private(&self) -> bool {
+        self.server.kind == ServerKind::Private
+    }
+
+    pub fn join_link(&self) -> Option<String> {
+        match self.game_id()? {
+            GameId::Place(place_id) => self.server.join_link(place_id),
+            GameId::Universe(_) => None,
+        }
+    }
+
+    fn is_same_join(&self, game_id: GameId, job_id: Option<&String>) -> bool {
+        if self.game_id() != Some(game_id) {
+            return false;
+        }
+
+        // "Launching experience" is logged without a job ID right before the
+        // "Joining game" line that carries it, so a missing ID on either side
+        // belongs to the same join.
+        match (self.server.job_id.as_ref(), job_id) {
+            (Some(current), Some(new)) => current == new,
+            _ => true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SessionChange {
+    /// A new join happened; presence and timer should start over.
+    Started(Session),
+    /// The previous session teleported the player into this one.
+    Teleported(Session),
+    /// More details about the current session's server became known.
+    Updated(Session),
+    /// Studio started testing the place; the test gets its own timer.
+    TestStarted(Session),
+    /// Studio went back to editing after a test.
+    TestStopped(Session),
+    /// The player left the session without closing Roblox.
+    Ended,
+}
+
+/// How far a teleport out of the current session has got.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Teleport {
+    /// The client started teleporting at this time.
+    Started(Instant),
+    /// The client left the old server for the new one.
+    Disconnected,
+}
+
+/// Folds log events into the session the client is currently in.
+pub struct SessionTracker {
+    roblox_type: RobloxType,
+    server: ServerInfo,
+    current: Option<Session>,
+    teleport: Option<Teleport>,
+    teleport_timeout: Duration,
+}
+
+impl SessionTracker {
+    pub fn new(roblox_type: RobloxType) -> Self {
+        SessionTracker {
+            roblox_type,
+            server: ServerInfo::default(),
+            current: None,
+            teleport: None,
+            teleport_timeout: TELEPORT_TIMEOUT,
+        }
+    }
+
+    /// Gives teleports `timeout` to disconnect instead of the default.
+    pub fn with_teleport_timeout(mut self, timeout: Duration) -> Self {
+        self.teleport_timeout = timeout;
+        self
+    }
+
+    pub fn roblox_type(&self) -> RobloxType {
+        self.roblox_type
+    }
+
+    pub fn current(&self) -> Option<&Session> {
+        self.current.as_ref()
+    }
+
+    /// Replays events that happened before we were watching and reports only
+    /// the session they leave the client in.
+    pub fn catch_up(&mut self, events: &[LogEvent]) -> Option<SessionChange> {
+        for event in events {
+            self.apply(event);
+        }
+
+        self.current.clone().map(SessionChange::Started)
+    }
+
+    pub fn apply(&mut self, event: &LogEvent) -> Option<SessionChange> {
+        if let LogEvent::JoinStarted { place_id, job_id } = event {
+            let teleported = match self.teleport.take() {
+                Some(Teleport::Started(at)) => at.elapsed() < self.teleport_timeout,
+                Some(Teleport::Disconnected) => true,
+                None => false,
+            } && self.current.is_some();
+
+            if let Some(session) = self.current.as_mut() {
+                if session.is_same_join(GameId::Place(*place_id), job_id.as_ref()) {
+                    // Don't let the repeated line reset what we know about the server
+                    if session.server.job_id.is_some() || job_id.is_none() {
+                        return None;
+                    }
+                    self.server.job_id = job_id.clone();
+                    session.server = self.server.clone();
+                    return Some(SessionChange::Updated(session.clone()));
+                }
+            }
+
+            // A teleport carries on the same stay, timer included
+            let started_at = self.current.as_ref().filter(|_| teleported).map(|s| s.started_at);
+
+            self.server.apply(event);
+            let mut session = self.start(Place::Published(GameId::Place(*place_id)));
+
+            return Some(match started_at {
+                Some(started_at) => {
+                    session.started_at = started_at;
+                    self.current = Some(session.clone());
+                    SessionChange::Teleported(session)
+                }
+                None => SessionChange::Started(session),
+            });
+        }
+
+        self.server.apply(event);
+
+        let studio = self.roblox_type == RobloxType::Studio;
+
+        match event {
+            // Unpublished places are logged with a place ID of 0
+            LogEvent::StudioPlaceOpened {
+                game_id: GameId::Place(PlaceId(0)),
+            } => None,
+            LogEvent::StudioPlaceOpened { game_id } if studio => {
+                let current = self.current.as_ref().and_then(Session::game_id);
+
+                // Studio repeats the place ID on many lines, so only a new place counts,
+                // and a universe ID is just more detail about a place already open
+                match (current, game_id) {
+                    (Some(current), _) if current == *game_id => return None,
+                    (Some(GameId::Place(_)), GameId::Universe(_)) => return None,
+                    _ => {}
+                }
+
+                Some(SessionChange::Started(self.start(Place::Published(*game_id))))
+            }
+            LogEvent::StudioLocalFileOpened { file_name } if studio => {
+                let place = Place::LocalFile(file_name.clone());
+                if self.current.as_ref().map(|s| &s.place) == Some(&place) {
+                    return None;
+                }
+
+                Some(SessionChange::Started(self.start(place)))
+            }
+            LogEvent::StudioPlaceClosed if studio => self.current.take().map(|_| SessionChange::Ended),
+            LogEvent::TeamCreateJoined if studio => {
+                let session = self.current.as_mut()?;
+                if session.team_create {
+                    return None;
+                }
+                session.team_create = true;
+                Some(SessionChange::Updated(session.clone()))
+            }
+            LogEvent::StudioTestStarted { mode } if studio => {
+                let session = self.current.as_mut()?;
+                if session.test.map(|test| test.mode) == Some(*mode) {
+                    return None;
+                }
+                session.test = Some(StudioTest {
+                    mode: *mode,
+                    started_at: unix_time(),
+                });
+                Some(SessionChange::TestStarted(session.clone()))
+            }
+            // Stopping a test tears down its local server, which looks like a disconnect
+            LogEvent::StudioTestStopped | LogEvent::Disconnected if studio => {
+                let session = self.current.as_mut()?;
+                session.test.take()?;
+                Some(SessionChange::TestStopped(session.clone()))
+            }
+            LogEvent::ServerEndpoints { .. } | LogEvent::JoinedServer { .. } | LogEvent::PlayerCount { .. } => {
+                let session = self.current.as_mut()?;
+                if session.server == self.server {
+                    return None;
+                }
+                session.server = self.server.clone();
+                Some(SessionChange::Updated(session.clone()))
+            }
+            LogEvent::Teleport { .. } => {
+                self.teleport = Some(Teleport::Started(Instant::now()));
+                None
+            }
+            // A teleport disconnects from the old server before joining the new one
+            LogEvent::Disconnected if self.teleport_disconnects() => {
+                self.teleport = Some(Teleport::Disconnected);
+                None
+            }
+            LogEvent::Disconnected | LogEvent::LeftGame | LogEvent::ReturnedToHome => {
+                self.teleport = None;
+                self.current.take().map(|_| SessionChange::Ended)
+            }
+            _ => None,
+        }
+    }
+
+    fn teleport_disconnects(&self) -> bool {
+        matches!(self.teleport, Some(Teleport::Started(at)) if at.elapsed() < self.teleport_timeout)
+    }
+
+    /// Restarts the current session's timer, for when it turns out to be a
+    /// new game after all.
+    pub fn restart_timer(&mut self, started_at: u64) {
+        if let Some(session) = self.current.as_mut() {
+            session.started_at = started_at;
+        }
+    }
+
+    fn start(&mut self, place: Place) -> Session {
+        let session = Session {
+            place,
+            server: self.server.clone(),
+            team_create: false,
+            test: None,
+            started_at: unix_time(),
+        };
+        self.current = Some(session.clone());
+        session
+    }
+}
+
+pub fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards")
+        .as_secs()
+}
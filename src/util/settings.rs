@@ -1,23 +1,23 @@
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fs;
 use std::path::PathBuf;
 use dirs::config_dir;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Settings {
     pub auto_start: bool,
     pub show_console: bool,
     pub custom_status_template: Option<String>,
-}
-
-impl Default for Settings {
-    fn default() -> Self {
-        Self {
-            auto_start: false,
-            show_console: false,
-            custom_status_template: None,
-        }
-    }
+    /// Show a button on the Discord profile that joins the same public server.
+    pub show_join_button: bool,
+    /// Use your own Discord application instead of the built-in one.
+    pub discord_application_id: Option<String>,
+    /// Application shown while using Roblox Studio, if not the one above.
+    pub studio_discord_application_id: Option<String>,
+    /// Renames the built-in image keys to the ones uploaded to your application.
+    pub asset_keys: HashMap<String, String>,
 }
 
 impl Settings {
@@ -28,6 +28,10 @@ impl Settings {
         path
     }
 
+    pub fn patterns_path() -> PathBuf {
+        Self::config_path().with_file_name("patterns.toml")
+    }
+
     pub fn load() -> Self {
         let config_path = Self::config_path();
         if config_path.exists() {
@@ -1,3 +1,4 @@
+use crate::util::discord::DiscordStatus;
 use tray_icon::{
     menu::{Menu, MenuItem, PredefinedMenuItem, CheckMenuItem},
     TrayIcon, TrayIconBuilder, Icon,
@@ -7,11 +8,15 @@ pub const MENU_QUIT_ID: &str = "quit";
 pub const MENU_AUTO_START_ID: &str = "auto_start";
 pub const MENU_SHOW_CONSOLE_ID: &str = "show_console";
 pub const MENU_OPEN_CONFIG_ID: &str = "open_config";
+pub const MENU_COPY_SERVER_LINK_ID: &str = "copy_server_link";
+pub const MENU_SHOW_JOIN_BUTTON_ID: &str = "show_join_button";
+const TOOLTIP: &str = "Roblox Discord Presence";
 
 pub struct TrayHandles {
     pub tray_icon: TrayIcon,
     pub auto_start: CheckMenuItem,
     pub show_console: CheckMenuItem,
+    pub show_join_button: CheckMenuItem,
 }
 
 pub fn setup_tray(settings: &crate::util::settings::Settings) -> TrayHandles {
@@ -21,14 +26,18 @@ pub fn setup_tray(settings: &crate::util::settings::Settings) -> TrayHandles {
     
     let auto_start = CheckMenuItem::with_id(MENU_AUTO_START_ID, "Auto Start", true, settings.auto_start, None);
     let show_console = CheckMenuItem::with_id(MENU_SHOW_CONSOLE_ID, "Show Console", true, settings.show_console, None);
+    let show_join_button = CheckMenuItem::with_id(MENU_SHOW_JOIN_BUTTON_ID, "Show Join Button", true, settings.show_join_button, None);
 
+    let copy_server_link = MenuItem::with_id(MENU_COPY_SERVER_LINK_ID, "Copy Server Link", true, None);
     let open_config = MenuItem::with_id(MENU_OPEN_CONFIG_ID, "Open Config File", true, None);
     let quit = MenuItem::with_id(MENU_QUIT_ID, "Quit", true, None);
     
     tray_menu.append_items(&[
         &auto_start,
         &show_console,
+        &show_join_button,
         &PredefinedMenuItem::separator(),
+        &copy_server_link,
         &open_config,
         &PredefinedMenuItem::separator(),
         &quit,
@@ -36,7 +45,7 @@ pub fn setup_tray(settings: &crate::util::settings::Settings) -> TrayHandles {
 
     let tray_icon = TrayIconBuilder::new()
         .with_menu(Box::new(tray_menu.clone()))
-        .with_tooltip("Roblox Discord Presence")
+        .with_tooltip(TOOLTIP)
         .with_icon(icon)
         .build()
         .unwrap();
@@ -45,6 +54,16 @@ pub fn setup_tray(settings: &crate::util::settings::Settings) -> TrayHandles {
         tray_icon,
         auto_start,
         show_console,
+        show_join_button,
+    }
+}
+
+/// Tooltip text saying which Discord account the presence goes to.
+pub fn status_tooltip(status: &DiscordStatus) -> String {
+    match status {
+        DiscordStatus::Connected(ready) => format!("{}\nConnected as {}", TOOLTIP, ready.username),
+        DiscordStatus::Disconnected => format!("{}\nWaiting for Discord", TOOLTIP),
+        DiscordStatus::Rejected(_) => format!("{}\nDiscord rejected the application ID", TOOLTIP),
     }
 }
 
@@ -4,10 +4,21 @@ use sysinfo::{System, ProcessRefreshKind, RefreshKind};
 
 use super::paths::RobloxType;
 
+/// A running Roblox client or Studio instance.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RobloxProcess {
+    pub pid: u32,
+    pub roblox_type: RobloxType,
+    /// Seconds since the Unix epoch.
+    pub start_time: u64,
+}
+
 #[derive(Debug)]
 pub enum WatcherEvent {
     RobloxStarted(RobloxType),
     RobloxClosed,
+    /// The set of running Roblox processes changed.
+    ProcessesChanged(Vec<RobloxProcess>),
 }
 
 pub fn spawn_watcher() -> UnboundedReceiver<WatcherEvent> {
@@ -20,14 +31,22 @@ pub fn spawn_watcher() -> UnboundedReceiver<WatcherEvent> {
         );
 
         let mut roblox_was_running: Option<RobloxType> = None;
+        let mut previous_processes: Vec<RobloxProcess> = Vec::new();
 
         loop {
             system.refresh_processes();
 
-            let roblox_is_running = get_running_roblox_type(&system);
+            let processes = get_running_roblox_processes(&system);
+            if processes != previous_processes {
+                println!("[WATCHER] Running processes: {:?}", processes);
+                let _ = tx.send(WatcherEvent::ProcessesChanged(processes.clone()));
+            }
+
+            // The most recently started process decides what we're doing
+            let roblox_is_running = processes.last().map(|p| p.roblox_type);
+            previous_processes = processes;
 
-            if roblox_is_running.is_some() && roblox_was_running.is_none() {
-                let rtype = roblox_is_running.unwrap();
+            if let (Some(rtype), None) = (roblox_is_running, roblox_was_running) {
                 println!("[WATCHER] Process Started: {:?}", rtype);
                 let _ = tx.send(WatcherEvent::RobloxStarted(rtype));
             } 
@@ -52,18 +71,33 @@ pub fn spawn_watcher() -> UnboundedReceiver<WatcherEvent> {
     rx
 }
 
-fn get_running_roblox_type(system: &System) -> Option<RobloxType> {
-    for (_pid, process) in system.processes() {
-        let name = process.name().to_lowercase();
-        
-        if name.contains("robloxstudio") {
-            return Some(RobloxType::Studio);
-        }
+/// Running Roblox processes, oldest first.
+fn get_running_roblox_processes(system: &System) -> Vec<RobloxProcess> {
+    let mut processes: Vec<RobloxProcess> = system
+        .processes()
+        .iter()
+        .filter_map(|(pid, process)| {
+            let name = process.name().to_lowercase();
 
-        if name.contains("robloxplayer") {
-            return Some(RobloxType::Player);
-        }
-    }
+            // Launchers and installers exit once the real client is up
+            let roblox_type = if name.contains("launcher") || name.contains("installer") {
+                return None;
+            } else if name.contains("robloxstudio") {
+                RobloxType::Studio
+            } else if name.contains("robloxplayer") {
+                RobloxType::Player
+            } else {
+                return None;
+            };
+
+            Some(RobloxProcess {
+                pid: pid.as_u32(),
+                roblox_type,
+                start_time: process.start_time(),
+            })
+        })
+        .collect();
 
-    None
+    processes.sort_by_key(|p| (p.start_time, p.pid));
+    processes
 }
\ No newline at end of file
@@ -0,0 +1,589 @@
+//! Talks to a mock Discord IPC server over a Unix socket and checks the exact
+//! frames the client sends.
+#![cfg(unix)]
+
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::net::Shutdown;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde_json::{json, Value};
+
+use roblox_discord_presence::util::discord::{ActivityButton, ApplicationIds, DiscordClient, DiscordStatus, PartySize, Presence};
+use roblox_discord_presence::util::discord_ipc::{IpcError, IpcTransport, PresenceTransport, ReadyInfo};
+use roblox_discord_presence::util::paths::RobloxType;
+
+const APP_ID: &str = "1234567890";
+const STUDIO_APP_ID: &str = "987654321";
+const REJECTED_APP_ID: &str = "0";
+const RATE_LIMIT_WINDOW: Duration = Duration::from_millis(300);
+
+/// How the mock answers SET_ACTIVITY.
+#[derive(Clone, Copy)]
+enum ActivityReply {
+    Accept,
+    Error,
+}
+
+struct MockDiscord {
+    socket_path: PathBuf,
+    frames: Receiver<(u32, Value)>,
+    connection: Arc<Mutex<Option<UnixStream>>>,
+}
+
+impl MockDiscord {
+    fn start(reply: ActivityReply) -> Self {
+        static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+        let socket_path = std::env::temp_dir().join(format!(
+            "discord-ipc-mock-{}-{}",
+            std::process::id(),
+            NEXT_ID.fetch_add(1, Ordering::SeqCst)
+        ));
+        Self::start_at(socket_path, reply)
+    }
+
+    fn start_at(socket_path: PathBuf, reply: ActivityReply) -> Self {
+        let _ = std::fs::remove_file(&socket_path);
+
+        let listener = UnixListener::bind(&socket_path).unwrap();
+        let (tx, frames) = mpsc::channel();
+        let connection = Arc::new(Mutex::new(None));
+        let current = connection.clone();
+
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else { return };
+                *current.lock().unwrap() = stream.try_clone().ok();
+
+                while let Some((opcode, payload)) = read_frame(&mut stream) {
+                    let _ = tx.send((opcode, payload.clone()));
+
+                    match opcode {
+                        0 if payload["client_id"] == REJECTED_APP_ID => {
+                            write_frame(&mut stream, 2, &json!({ "code": 4000, "message": "Invalid Client ID" }));
+                        }
+                        0 => write_frame(&mut stream, 1, &ready_payload()),
+                        1 => write_frame(&mut stream, 1, &command_reply(&payload, reply)),
+                        3 => write_frame(&mut stream, 4, &payload),
+                        _ => {}
+                    }
+                }
+            }
+        });
+
+        MockDiscord {
+            socket_path,
+            frames,
+            connection,
+        }
+    }
+
+    /// Hangs up and stops listening, as if Discord quit. Returns the socket
+    /// path so Discord can be started again.
+    fn quit(self) -> PathBuf {
+        if let Some(stream) = self.connection.lock().unwrap().take() {
+            let _ = stream.shutdown(Shutdown::Both);
+        }
+        self.socket_path.clone()
+    }
+
+    fn transport(&self, app_id: &str) -> IpcTransport {
+        IpcTransport::with_socket_path(app_id, self.socket_path.clone())
+    }
+
+    fn next_frame(&self) -> (u32, Value) {
+        self.frames
+            .recv_timeout(Duration::from_secs(5))
+            .expect("mock Discord received no frame")
+    }
+
+    fn assert_no_frame(&self) {
+        if let Ok(frame) = self.frames.recv_timeout(Duration::from_millis(100)) {
+            panic!("expected nothing to be sent, got {:?}", frame);
+        }
+    }
+}
+
+impl Drop for MockDiscord {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.socket_path);
+    }
+}
+
+fn read_frame(stream: &mut UnixStream) -> Option<(u32, Value)> {
+    let mut header = [0u8; 8];
+    stream.read_exact(&mut header).ok()?;
+    let opcode = u32::from_le_bytes(header[..4].try_into().unwrap());
+    let len = u32::from_le_bytes(header[4..].try_into().unwrap());
+
+    let mut body = vec![0u8; len as usize];
+    stream.read_exact(&mut body).ok()?;
+    Some((opcode, serde_json::from_slice(&body).unwrap()))
+}
+
+fn write_frame(stream: &mut UnixStream, opcode: u32, payload: &Value) {
+    let body = payload.to_string();
+    let mut frame = Vec::new();
+    frame.extend_from_slice(&opcode.to_le_bytes());
+    frame.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    frame.extend_from_slice(body.as_bytes());
+    let _ = stream.write_all(&frame);
+}
+
+fn ready_payload() -> Value {
+    json!({
+        "cmd": "DISPATCH",
+        "evt": "READY",
+        "data": {
+            "v": 1,
+            "config": { "cdn_host": "cdn.discordapp.com", "api_endpoint": "//discord.com/api", "environment": "production" },
+            "user": { "id": "42", "username": "builderman", "discriminator": "0" }
+        },
+        "nonce": null
+    })
+}
+
+fn command_reply(command: &Value, reply: ActivityReply) -> Value {
+    match reply {
+        ActivityReply::Accept => json!({
+            "cmd": command["cmd"],
+            "data": command["args"]["activity"],
+            "evt": null,
+            "nonce": command["nonce"]
+        }),
+        ActivityReply::Error => json!({
+            "cmd": command["cmd"],
+            "data": { "code": 4000, "message": "child \"activity\" fails because [child \"buttons\" fails]" },
+            "evt": "ERROR",
+            "nonce": command["nonce"]
+        }),
+    }
+}
+
+fn game_presence() -> Presence {
+    Presence::new("Natural Disaster Survival", "by Stickmasterluke", "https://tr.rbxcdn.com/thumb.png")
+        .start_time(1_714_586_400)
+        .party(Some(PartySize { current: 6, max: 12 }))
+        .buttons(vec![
+            ActivityButton::new("View Game", "https://www.roblox.com/games/189707"),
+            ActivityButton::new("Join Server", "https://www.roblox.com/games/start?placeId=189707&gameInstanceId=abc"),
+        ])
+}
+
+#[test]
+fn handshake_sends_version_and_client_id() {
+    let mock = MockDiscord::start(ActivityReply::Accept);
+    let mut transport = mock.transport(APP_ID);
+
+    transport.connect().unwrap();
+
+    assert_eq!(mock.next_frame(), (0, json!({ "v": 1, "client_id": APP_ID })));
+}
+
+#[test]
+fn ready_payload_is_parsed() {
+    let mock = MockDiscord::start(ActivityReply::Accept);
+    let mut transport = mock.transport(APP_ID);
+
+    assert_eq!(
+        transport.connect().unwrap(),
+        ReadyInfo {
+            user_id: "42".to_string(),
+            username: "builderman".to_string(),
+            environment: Some("production".to_string()),
+        }
+    );
+}
+
+#[test]
+fn client_reports_the_connected_user() {
+    let mock = MockDiscord::start(ActivityReply::Accept);
+    let mut client = DiscordClient::with_transport(APP_ID, Box::new(mock.transport(APP_ID)));
+
+    assert_eq!(*client.status(), DiscordStatus::Disconnected);
+    client.update_presence(&Presence::new("Roblox", "In menus", "roblox_logo"));
+
+    match client.status() {
+        DiscordStatus::Connected(ready) => assert_eq!(ready.username, "builderman"),
+        other => panic!("expected to be connected, got {:?}", other),
+    }
+}
+
+#[test]
+fn client_reports_a_rejected_application_id() {
+    let mock = MockDiscord::start(ActivityReply::Accept);
+    let mut client = DiscordClient::with_transport(REJECTED_APP_ID, Box::new(mock.transport(REJECTED_APP_ID)));
+
+    client.update_presence(&Presence::new("Roblox", "In menus", "roblox_logo"));
+
+    match client.status() {
+        DiscordStatus::Rejected(message) => assert!(message.contains("application ID 0"), "{}", message),
+        other => panic!("expected the application ID to be rejected, got {:?}", other),
+    }
+}
+
+#[test]
+fn set_activity_sends_the_full_presence() {
+    let mock = MockDiscord::start(ActivityReply::Accept);
+    let mut client = DiscordClient::with_transport(APP_ID, Box::new(mock.transport(APP_ID)));
+
+    client.update_presence(&game_presence());
+
+    assert_eq!(mock.next_frame().0, 0);
+    let (opcode, command) = mock.next_frame();
+    assert_eq!(opcode, 1);
+    assert_eq!(command["cmd"], "SET_ACTIVITY");
+    assert_eq!(command["args"]["pid"], std::process::id());
+    assert!(command["nonce"].is_string());
+    assert_eq!(
+        command["args"]["activity"],
+        json!({
+            "details": "Natural Disaster Survival",
+            "state": "by Stickmasterluke",
+            "assets": {
+                "large_image": "https://tr.rbxcdn.com/thumb.png",
+                "large_text": "Natural Disaster Survival"
+            },
+            "timestamps": { "start": 1_714_586_400 },
+            "party": { "size": [6, 12] },
+            "buttons": [
+                { "label": "View Game", "url": "https://www.roblox.com/games/189707" },
+                { "label": "Join Server", "url": "https://www.roblox.com/games/start?placeId=189707&gameInstanceId=abc" }
+            ]
+        })
+    );
+}
+
+#[test]
+fn small_text_adds_the_small_image() {
+    let mock = MockDiscord::start(ActivityReply::Accept);
+    let mut client = DiscordClient::with_transport(APP_ID, Box::new(mock.transport(APP_ID)));
+
+    client.update_presence(&Presence::new("Roblox Studio", "Editing", "roblox_studio").small_text("Play Solo"));
+
+    mock.next_frame();
+    assert_eq!(
+        mock.next_frame().1["args"]["activity"],
+        json!({
+            "details": "Roblox Studio",
+            "state": "Editing",
+            "assets": {
+                "large_image": "roblox_studio",
+                "large_text": "Roblox Studio",
+                "small_image": "roblox_logo",
+                "small_text": "Play Solo"
+            }
+        })
+    );
+}
+
+#[test]
+fn clear_presence_sends_a_null_activity() {
+    let mock = MockDiscord::start(ActivityReply::Accept);
+    let mut client = DiscordClient::with_transport(APP_ID, Box::new(mock.transport(APP_ID)))
+        .with_rate_limit(1, Duration::ZERO);
+
+    client.update_presence(&Presence::new("Roblox", "In menus", "roblox_logo"));
+    client.clear_presence();
+
+    mock.next_frame();
+    mock.next_frame();
+    let (opcode, command) = mock.next_frame();
+    assert_eq!(opcode, 1);
+    assert_eq!(command["cmd"], "SET_ACTIVITY");
+    assert_eq!(command["args"]["activity"], Value::Null);
+}
+
+#[test]
+fn ping_waits_for_pong() {
+    let mock = MockDiscord::start(ActivityReply::Accept);
+    let mut transport = mock.transport(APP_ID);
+
+    transport.connect().unwrap();
+    transport.ping().unwrap();
+
+    mock.next_frame();
+    assert_eq!(mock.next_frame().0, 3);
+}
+
+#[test]
+fn close_sends_a_close_frame() {
+    let mock = MockDiscord::start(ActivityReply::Accept);
+    let mut transport = mock.transport(APP_ID);
+
+    transport.connect().unwrap();
+    transport.close();
+
+    mock.next_frame();
+    assert_eq!(mock.next_frame(), (2, json!({})));
+}
+
+/// Never finds Discord, and notes when it looked.
+struct MissingDiscord {
+    attempts: Arc<Mutex<Vec<Instant>>>,
+}
+
+impl PresenceTransport for MissingDiscord {
+    fn set_client_id(&mut self, _client_id: &str) {}
+
+    fn connect(&mut self) -> Result<ReadyInfo, IpcError> {
+        self.attempts.lock().unwrap().push(Instant::now());
+        Err(IpcError::NotRunning)
+    }
+
+    fn set_activity(&mut self, _activity: Option<&Value>) -> Result<(), IpcError> {
+        Err(IpcError::NotRunning)
+    }
+
+    fn ping(&mut self) -> Result<(), IpcError> {
+        Err(IpcError::NotRunning)
+    }
+
+    fn close(&mut self) {}
+}
+
+#[test]
+fn failed_connects_double_the_retry_delay() {
+    let attempts = Arc::new(Mutex::new(Vec::new()));
+    let transport = MissingDiscord {
+        attempts: attempts.clone(),
+    };
+    let mut client = DiscordClient::with_transport(APP_ID, Box::new(transport))
+        .with_retry_delays(Duration::from_millis(40), Duration::from_millis(160));
+
+    client.update_presence(&Presence::new("Roblox", "In menus", "roblox_logo"));
+    let started = Instant::now();
+    while started.elapsed() < Duration::from_millis(500) {
+        client.tick();
+        thread::sleep(Duration::from_millis(2));
+    }
+
+    let attempts = attempts.lock().unwrap();
+    let gaps: Vec<Duration> = attempts.windows(2).map(|pair| pair[1] - pair[0]).collect();
+    assert!(gaps.len() >= 4, "only {} attempts", attempts.len());
+    for (gap, expected) in gaps.iter().zip([40, 80, 160, 160]) {
+        let expected = Duration::from_millis(expected);
+        assert!(
+            *gap >= expected && *gap < expected + Duration::from_millis(40),
+            "waited {:?} instead of {:?}",
+            gap,
+            expected
+        );
+    }
+    assert_eq!(*client.status(), DiscordStatus::Disconnected);
+}
+
+#[test]
+fn presence_is_restored_once_discord_is_back() {
+    let retry_delay = Duration::from_millis(500);
+    let mock = MockDiscord::start(ActivityReply::Accept);
+    let mut client = DiscordClient::with_transport(APP_ID, Box::new(mock.transport(APP_ID)))
+        .with_rate_limit(1, Duration::ZERO)
+        .with_retry_delays(retry_delay, retry_delay * 4);
+
+    client.update_presence(&Presence::new("Roblox", "In menus", "roblox_logo"));
+    mock.next_frame();
+    mock.next_frame();
+    assert!(matches!(client.status(), DiscordStatus::Connected(_)));
+
+    let socket_path = mock.quit();
+    // Noticing Discord is gone takes a failed write
+    client.update_presence(&game_presence());
+    client.tick();
+    assert_eq!(*client.status(), DiscordStatus::Disconnected);
+
+    // Discord is back, but the client waits out its back-off first
+    let mock = MockDiscord::start_at(socket_path, ActivityReply::Accept);
+    client.tick();
+    mock.assert_no_frame();
+
+    thread::sleep(retry_delay);
+    client.tick();
+
+    assert_eq!(mock.next_frame(), (0, json!({ "v": 1, "client_id": APP_ID })));
+    let (opcode, command) = mock.next_frame();
+    assert_eq!(opcode, 1);
+    assert_eq!(command["args"]["activity"], game_presence().to_activity(&HashMap::new()));
+    assert!(matches!(client.status(), DiscordStatus::Connected(_)));
+}
+
+#[test]
+fn rejected_application_id_is_reported() {
+    let mock = MockDiscord::start(ActivityReply::Accept);
+    let mut transport = mock.transport(REJECTED_APP_ID);
+
+    match transport.connect() {
+        Err(e @ IpcError::Closed { .. }) => assert!(e.is_invalid_client_id()),
+        other => panic!("expected the handshake to be closed, got {:?}", other),
+    }
+}
+
+#[test]
+fn activity_errors_are_reported() {
+    let mock = MockDiscord::start(ActivityReply::Error);
+    let mut transport = mock.transport(APP_ID);
+
+    transport.connect().unwrap();
+    let activity = game_presence().to_activity(&HashMap::new());
+
+    match transport.set_activity(Some(&activity)) {
+        Err(IpcError::Rejected { code, .. }) => assert_eq!(code, 4000),
+        other => panic!("expected the activity to be rejected, got {:?}", other),
+    }
+}
+
+#[test]
+fn missing_discord_is_not_running() {
+    let socket_path = std::env::temp_dir().join(format!("discord-ipc-missing-{}", std::process::id()));
+    let mut transport = IpcTransport::with_socket_path(APP_ID, socket_path);
+
+    assert!(matches!(transport.connect(), Err(IpcError::NotRunning)));
+}
+
+#[test]
+fn asset_keys_rename_the_images() {
+    let mock = MockDiscord::start(ActivityReply::Accept);
+    let mut client = DiscordClient::with_transport(APP_ID, Box::new(mock.transport(APP_ID)));
+    let asset_keys = HashMap::from([
+        ("roblox_studio".to_string(), "studio_art".to_string()),
+        ("roblox_logo".to_string(), "logo_art".to_string()),
+    ]);
+
+    client.configure(ApplicationIds::new(Some(APP_ID), None), &asset_keys);
+    client.update_presence(&Presence::new("Roblox Studio", "Editing", "roblox_studio").small_text("Play Solo"));
+
+    mock.next_frame();
+    let assets = &mock.next_frame().1["args"]["activity"]["assets"];
+    assert_eq!(assets["large_image"], "studio_art");
+    assert_eq!(assets["small_image"], "logo_art");
+}
+
+#[test]
+fn changing_the_application_reconnects_with_the_presence() {
+    let mock = MockDiscord::start(ActivityReply::Accept);
+    let mut client = DiscordClient::with_transport(APP_ID, Box::new(mock.transport(APP_ID)));
+
+    client.update_presence(&Presence::new("Roblox", "In menus", "roblox_logo"));
+    mock.next_frame();
+    mock.next_frame();
+
+    client.configure(ApplicationIds::new(Some(STUDIO_APP_ID), None), &HashMap::new());
+
+    assert_eq!(mock.next_frame(), (2, json!({})));
+    assert_eq!(mock.next_frame(), (0, json!({ "v": 1, "client_id": STUDIO_APP_ID })));
+    let (opcode, command) = mock.next_frame();
+    assert_eq!(opcode, 1);
+    assert_eq!(command["args"]["activity"]["state"], "In menus");
+}
+
+#[test]
+fn studio_is_shown_as_its_own_application() {
+    let mock = MockDiscord::start(ActivityReply::Accept);
+    let mut client = DiscordClient::with_transport(APP_ID, Box::new(mock.transport(APP_ID)));
+    client.configure(ApplicationIds::new(Some(APP_ID), Some(STUDIO_APP_ID)), &HashMap::new());
+
+    client.set_roblox_type(RobloxType::Player);
+    client.update_presence(&Presence::new("Roblox", "Loading", "roblox_logo"));
+    assert_eq!(mock.next_frame(), (0, json!({ "v": 1, "client_id": APP_ID })));
+    mock.next_frame();
+
+    client.set_roblox_type(RobloxType::Studio);
+    client.update_presence(&Presence::new("Roblox Studio", "Developing", "roblox_studio"));
+
+    assert_eq!(mock.next_frame(), (2, json!({})));
+    assert_eq!(mock.next_frame(), (0, json!({ "v": 1, "client_id": STUDIO_APP_ID })));
+    assert_eq!(mock.next_frame().1["args"]["activity"]["details"], "Roblox Studio");
+}
+
+#[test]
+fn studio_shares_the_player_application_by_default() {
+    let ids = ApplicationIds::new(Some(" 1234567890 "), Some(""));
+
+    assert_eq!(ids.get(RobloxType::Player), APP_ID);
+    assert_eq!(ids.get(RobloxType::Studio), APP_ID);
+}
+
+#[test]
+fn updates_within_the_rate_limit_are_coalesced() {
+    let mock = MockDiscord::start(ActivityReply::Accept);
+    let mut client = DiscordClient::with_transport(APP_ID, Box::new(mock.transport(APP_ID)))
+        .with_rate_limit(1, RATE_LIMIT_WINDOW);
+
+    client.update_presence(&Presence::new("Roblox", "Loading", "roblox_logo"));
+    mock.next_frame();
+    assert_eq!(mock.next_frame().1["args"]["activity"]["state"], "Loading");
+
+    client.update_presence(&Presence::new("Natural Disaster Survival", "In Lobby", "roblox_logo"));
+    client.update_presence(&Presence::new("Natural Disaster Survival", "In Arena", "roblox_logo"));
+    client.tick();
+    mock.assert_no_frame();
+
+    thread::sleep(RATE_LIMIT_WINDOW);
+    client.tick();
+
+    let (opcode, command) = mock.next_frame();
+    assert_eq!(opcode, 1);
+    assert_eq!(command["args"]["activity"]["state"], "In Arena");
+    client.tick();
+    mock.assert_no_frame();
+}
+
+#[test]
+fn updates_up_to_the_rate_limit_are_sent_right_away() {
+    let mock = MockDiscord::start(ActivityReply::Accept);
+    let mut client = DiscordClient::with_transport(APP_ID, Box::new(mock.transport(APP_ID)))
+        .with_rate_limit(2, RATE_LIMIT_WINDOW);
+
+    client.update_presence(&Presence::new("Roblox", "Loading", "roblox_logo"));
+    mock.next_frame();
+    assert_eq!(mock.next_frame().1["args"]["activity"]["state"], "Loading");
+    client.update_presence(&Presence::new("Natural Disaster Survival", "In Lobby", "roblox_logo"));
+    assert_eq!(mock.next_frame().1["args"]["activity"]["state"], "In Lobby");
+
+    client.update_presence(&Presence::new("Natural Disaster Survival", "In Arena", "roblox_logo"));
+    mock.assert_no_frame();
+
+    thread::sleep(RATE_LIMIT_WINDOW);
+    client.tick();
+    assert_eq!(mock.next_frame().1["args"]["activity"]["state"], "In Arena");
+}
+
+#[test]
+fn clearing_within_the_rate_limit_waits_its_turn() {
+    let mock = MockDiscord::start(ActivityReply::Accept);
+    let mut client = DiscordClient::with_transport(APP_ID, Box::new(mock.transport(APP_ID)))
+        .with_rate_limit(1, RATE_LIMIT_WINDOW);
+
+    client.update_presence(&Presence::new("Roblox", "In menus", "roblox_logo"));
+    mock.next_frame();
+    mock.next_frame();
+
+    client.clear_presence();
+    mock.assert_no_frame();
+
+    thread::sleep(RATE_LIMIT_WINDOW);
+    client.tick();
+    assert_eq!(mock.next_frame().1["args"]["activity"], Value::Null);
+}
+
+#[test]
+fn unchanged_presence_is_not_resent() {
+    let mock = MockDiscord::start(ActivityReply::Accept);
+    let mut client = DiscordClient::with_transport(APP_ID, Box::new(mock.transport(APP_ID)))
+        .with_rate_limit(1, Duration::ZERO);
+
+    client.update_presence(&game_presence());
+    mock.next_frame();
+    mock.next_frame();
+
+    client.update_presence(&game_presence());
+    client.update_party(Some(PartySize { current: 6, max: 12 }));
+    mock.assert_no_frame();
+}
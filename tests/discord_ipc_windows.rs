@@ -0,0 +1,82 @@
+//! Checks the transport against named pipes, which unlike Unix sockets have
+//! no read timeout of their own.
+#![cfg(windows)]
+
+use std::io;
+use std::path::PathBuf;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use windows::core::HSTRING;
+use windows::Win32::Foundation::{CloseHandle, HANDLE};
+use windows::Win32::Storage::FileSystem::PIPE_ACCESS_DUPLEX;
+use windows::Win32::System::Pipes::{ConnectNamedPipe, CreateNamedPipeW, PIPE_TYPE_BYTE, PIPE_UNLIMITED_INSTANCES, PIPE_WAIT};
+
+use roblox_discord_presence::util::discord_ipc::{IpcError, IpcTransport, PresenceTransport};
+
+const APP_ID: &str = "1234567890";
+
+/// A pipe server that accepts the client and then never answers.
+struct SilentDiscord {
+    pipe_path: PathBuf,
+    handle: HANDLE,
+}
+
+impl SilentDiscord {
+    fn start() -> Self {
+        let pipe_path = PathBuf::from(format!(r"\\.\pipe\discord-ipc-test-{}", std::process::id()));
+        let handle = unsafe {
+            CreateNamedPipeW(
+                &HSTRING::from(pipe_path.as_path()),
+                PIPE_ACCESS_DUPLEX,
+                PIPE_TYPE_BYTE | PIPE_WAIT,
+                PIPE_UNLIMITED_INSTANCES,
+                4096,
+                4096,
+                0,
+                None,
+            )
+        };
+        assert!(!handle.is_invalid(), "couldn't create the mock pipe");
+
+        SilentDiscord { pipe_path, handle }
+    }
+}
+
+impl Drop for SilentDiscord {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = CloseHandle(self.handle);
+        }
+    }
+}
+
+#[test]
+fn missing_discord_is_not_running() {
+    let mut transport = IpcTransport::with_socket_path(APP_ID, PathBuf::from(r"\\.\pipe\discord-ipc-does-not-exist"));
+
+    assert!(matches!(transport.connect(), Err(IpcError::NotRunning)));
+}
+
+#[test]
+fn unresponsive_discord_times_out() {
+    let discord = SilentDiscord::start();
+    let mut transport = IpcTransport::with_socket_path(APP_ID, discord.pipe_path.clone());
+
+    let (done, result) = mpsc::channel();
+    thread::spawn(move || {
+        let _ = done.send(transport.connect().map(|_| ()));
+    });
+    // The client connecting completes the server side of the pipe
+    let _ = unsafe { ConnectNamedPipe(discord.handle, None) };
+
+    let result = result
+        .recv_timeout(Duration::from_secs(15))
+        .expect("connect is still waiting on a silent pipe");
+    assert!(
+        matches!(&result, Err(IpcError::Io(e)) if e.kind() == io::ErrorKind::TimedOut),
+        "got {:?}",
+        result
+    );
+}
@@ -0,0 +1,73 @@
+//! Runs every log in `tests/fixtures/logs` through the parser and compares the
+//! events with the `.events` file next to it. Set `UPDATE_GOLDEN=1` to rewrite
+//! the expected files after an intended change, then review the diff.
+//!
+//! Fixtures must be trimmed excerpts of real Roblox logs, with IDs, IPs and
+//! user names replaced. Lines written to match a pattern only test the
+//! pattern against itself. The Player fixtures are still reconstructed from
+//! the line prefixes Bloxstrap matches in real logs, and there are no Studio
+//! fixtures yet; both should give way to captured excerpts.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use roblox_discord_presence::util::log_parser::LogReader;
+use roblox_discord_presence::util::log_patterns::PatternRegistry;
+
+fn fixture_logs() -> Vec<PathBuf> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/logs");
+
+    let mut logs: Vec<PathBuf> = fs::read_dir(&dir)
+        .unwrap_or_else(|e| panic!("Failed to read {}: {}", dir.display(), e))
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().map(|e| e == "log").unwrap_or(false))
+        .collect();
+
+    logs.sort();
+    logs
+}
+
+fn parse_fixture(registry: &PatternRegistry, path: &Path) -> String {
+    let mut reader = LogReader::new(path).unwrap();
+
+    reader
+        .get_new_lines()
+        .iter()
+        .filter_map(|line| registry.parse_line(line))
+        .map(|event| format!("{:?}\n", event))
+        .collect()
+}
+
+#[test]
+fn log_events_match_golden_files() {
+    // Built-in patterns only, so a local patterns.toml can't change the result
+    let registry = PatternRegistry::builtin();
+    let update = env::var_os("UPDATE_GOLDEN").is_some();
+    let logs = fixture_logs();
+    let mut failures = Vec::new();
+
+    assert!(!logs.is_empty(), "No fixture logs found");
+
+    for log in &logs {
+        let actual = parse_fixture(&registry, log);
+        let expected_path = log.with_extension("events");
+
+        if update {
+            fs::write(&expected_path, &actual).unwrap();
+            continue;
+        }
+
+        let expected = fs::read_to_string(&expected_path).unwrap_or_default();
+        if actual != expected {
+            failures.push(format!(
+                "{}\n--- expected\n{}--- actual\n{}",
+                log.display(),
+                expected,
+                actual
+            ));
+        }
+    }
+
+    assert!(failures.is_empty(), "Parser output changed:\n\n{}", failures.join("\n"));
+}
@@ -0,0 +1,111 @@
+//! Follows a log file in a temp directory through the ways Roblox writes,
+//! rotates and replaces it.
+
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use roblox_discord_presence::util::log_parser::LogReader;
+
+struct TempLog {
+    dir: PathBuf,
+    path: PathBuf,
+}
+
+impl TempLog {
+    fn new(content: &[u8]) -> Self {
+        static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+        let dir = std::env::temp_dir().join(format!(
+            "rdp-log-reader-{}-{}",
+            std::process::id(),
+            NEXT_ID.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("Player_last.log");
+        fs::write(&path, content).unwrap();
+
+        TempLog { dir, path }
+    }
+
+    fn append(&self, content: &[u8]) {
+        let mut file = OpenOptions::new().append(true).open(&self.path).unwrap();
+        file.write_all(content).unwrap();
+    }
+
+    /// Puts a new file in place of the log, as Roblox does for a new session.
+    fn replace(&self, content: &[u8]) {
+        let new_path = self.dir.join("new.log");
+        fs::write(&new_path, content).unwrap();
+        fs::rename(&new_path, &self.path).unwrap();
+    }
+
+    fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempLog {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+#[test]
+fn character_split_across_reads_is_decoded_whole() {
+    let log = TempLog::new(b"");
+    let mut reader = LogReader::new(log.path()).unwrap();
+    let line = "[FLog::Output] Joined Caf\u{e9} Roblox\n".as_bytes();
+    let split = line.iter().position(|&b| b >= 0x80).unwrap() + 1;
+
+    log.append(&line[..split]);
+    assert!(reader.get_new_lines().is_empty());
+    log.append(&line[split..]);
+
+    assert_eq!(reader.get_new_lines(), vec!["[FLog::Output] Joined Caf\u{e9} Roblox"]);
+}
+
+#[test]
+fn lines_are_returned_once_complete() {
+    let log = TempLog::new(b"first\nsecond\nthi");
+    let mut reader = LogReader::new(log.path()).unwrap();
+
+    assert_eq!(reader.get_new_lines(), vec!["first", "second"]);
+    log.append(b"rd\n\nfourth\n");
+    assert_eq!(reader.get_new_lines(), vec!["third", "", "fourth"]);
+    assert!(reader.get_new_lines().is_empty());
+}
+
+#[test]
+fn truncated_log_is_read_from_the_start() {
+    let log = TempLog::new(b"first line\nsecond line\n");
+    let mut reader = LogReader::new(log.path()).unwrap();
+    reader.get_new_lines();
+
+    fs::write(log.path(), b"over\n").unwrap();
+
+    assert_eq!(reader.get_new_lines(), vec!["over"]);
+}
+
+#[test]
+fn replaced_log_is_reopened() {
+    let log = TempLog::new(b"old\n");
+    let mut reader = LogReader::new(log.path()).unwrap();
+    reader.get_new_lines();
+
+    // Longer than what was read, so only the file's identity gives it away
+    log.replace(b"a whole new session\n");
+
+    assert_eq!(reader.get_new_lines(), vec!["a whole new session"]);
+}
+
+#[test]
+fn unfinished_last_line_is_kept_for_the_end() {
+    let log = TempLog::new(b"done\nnot done");
+    let mut reader = LogReader::new(log.path()).unwrap();
+
+    assert_eq!(reader.get_new_lines(), vec!["done"]);
+    assert_eq!(reader.take_partial_line().as_deref(), Some("not done"));
+    assert_eq!(reader.take_partial_line(), None);
+}
@@ -0,0 +1,106 @@
+//! Checks the pieces the replay command is built from: its arguments and the
+//! timestamps it paces lines by.
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+use roblox_discord_presence::util::log_parser::line_timestamp;
+use roblox_discord_presence::util::paths::RobloxType;
+use roblox_discord_presence::util::replay::ReplayOptions;
+
+fn options(args: &[&str]) -> Result<ReplayOptions, String> {
+    let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+    ReplayOptions::from_args(&args)
+}
+
+fn timestamp(line: &str) -> Option<Duration> {
+    line_timestamp(line).map(|(_, time)| time)
+}
+
+#[test]
+fn timestamp_is_time_since_the_epoch() {
+    let line = "2024-05-01T18:00:01.450Z,1.450000,1a2b,6 [FLog::Output] ! Joining game";
+
+    assert_eq!(
+        line_timestamp(line),
+        Some(("2024-05-01T18:00:01.450Z", Duration::from_millis(1_714_586_401_450)))
+    );
+    assert_eq!(timestamp("1970-01-01T00:00:00.000Z,0"), Some(Duration::ZERO));
+    assert_eq!(timestamp("2024-02-29T23:59:59.000Z,0"), Some(Duration::from_secs(1_709_251_199)));
+}
+
+#[test]
+fn out_of_range_timestamps_are_rejected() {
+    for line in [
+        "2024-13-45T99:99:99.000Z,0",
+        "2024-00-01T12:00:00.000Z,0",
+        "2024-05-00T12:00:00.000Z,0",
+        "2024-05-32T12:00:00.000Z,0",
+        "2024-05-01T24:00:00.000Z,0",
+        "2024-05-01T12:60:00.000Z,0",
+        "2024-05-01T12:00:60.000Z,0",
+        "2024-05-01T12:00:NaN,0",
+        "1969-12-31T23:59:59.000Z,0",
+    ] {
+        assert_eq!(line_timestamp(line), None, "{}", line);
+    }
+}
+
+#[test]
+fn huge_years_do_not_overflow() {
+    assert_eq!(timestamp("999999999999-01-01T00:00:00.000Z,0"), None);
+    assert_eq!(timestamp("9223372036854775807-12-31T23:59:59.999Z,0"), None);
+}
+
+#[test]
+fn lines_without_a_timestamp_have_none() {
+    assert_eq!(line_timestamp(""), None);
+    assert_eq!(line_timestamp("[FLog::Output] ! Joining game"), None);
+    assert_eq!(line_timestamp("2024-05-01 18:00:01,1.45"), None);
+}
+
+#[test]
+fn log_path_is_required() {
+    assert!(options(&[]).is_err());
+    assert!(options(&["--realtime"]).is_err());
+}
+
+#[test]
+fn defaults_replay_instantly_as_the_player() {
+    let options = options(&["log.txt"]).unwrap();
+
+    assert_eq!(options.log_path, PathBuf::from("log.txt"));
+    assert_eq!(options.roblox_type, RobloxType::Player);
+    assert!(!options.realtime);
+    assert_eq!(options.speed, 1.0);
+}
+
+#[test]
+fn client_type_comes_from_the_file_name_unless_given() {
+    let studio_log = "0.600.0.6000123_20240101T000000Z_Studio_1A2B3_last.log";
+
+    assert_eq!(options(&[studio_log]).unwrap().roblox_type, RobloxType::Studio);
+    assert_eq!(options(&["--studio", "log.txt"]).unwrap().roblox_type, RobloxType::Studio);
+}
+
+#[test]
+fn speed_implies_realtime() {
+    let options = options(&["log.txt", "--speed", "10"]).unwrap();
+
+    assert!(options.realtime);
+    assert_eq!(options.speed, 10.0);
+}
+
+#[test]
+fn bad_arguments_are_rejected() {
+    for args in [
+        &["log.txt", "--speed"][..],
+        &["log.txt", "--speed", "0"],
+        &["log.txt", "--speed", "-2"],
+        &["log.txt", "--speed", "fast"],
+        &["log.txt", "--loop"],
+        &["log.txt", "other.txt"],
+    ] {
+        assert!(options(args).is_err(), "{:?}", args);
+    }
+}
@@ -0,0 +1,230 @@
+//! Feeds log events through `SessionTracker` and checks the sessions it
+//! reports.
+
+use roblox_discord_presence::util::log_parser::{LogEvent, ServerKind};
+use roblox_discord_presence::util::log_patterns::{EventKind, LogPattern, PatternRegistry};
+use roblox_discord_presence::util::paths::RobloxType;
+use roblox_discord_presence::util::roblox_api::{GameId, PlaceId};
+use roblox_discord_presence::util::session::{Session, SessionChange, SessionTracker};
+
+use std::thread;
+use std::time::Duration;
+
+fn join(place_id: u64, job_id: &str) -> LogEvent {
+    LogEvent::JoinStarted {
+        place_id: PlaceId(place_id),
+        job_id: Some(job_id.to_string()),
+    }
+}
+
+fn private_server_marker() -> LogEvent {
+    LogEvent::ServerKindDetected {
+        kind: ServerKind::Private,
+        access_code: Some("abcdef01-2345-6789-abcd-ef0123456789".to_string()),
+    }
+}
+
+fn started(change: Option<SessionChange>) -> Session {
+    match change {
+        Some(SessionChange::Started(session)) => session,
+        other => panic!("expected a new session, got {:?}", other),
+    }
+}
+
+fn teleported(change: Option<SessionChange>) -> Session {
+    match change {
+        Some(SessionChange::Teleported(session)) => session,
+        other => panic!("expected a teleport, got {:?}", other),
+    }
+}
+
+#[test]
+fn rejoining_the_same_place_starts_a_new_session() {
+    let mut tracker = SessionTracker::new(RobloxType::Player);
+    let first = started(tracker.apply(&join(1111111111, "0a1b2c3d-4e5f-6a7b-8c9d-0e1f2a3b4c5d")));
+
+    assert_eq!(tracker.apply(&LogEvent::LeftGame), Some(SessionChange::Ended));
+    // The timer counts whole seconds
+    thread::sleep(Duration::from_millis(1100));
+    let second = started(tracker.apply(&join(1111111111, "9f8e7d6c-5b4a-3928-1706-f5e4d3c2b1a0")));
+
+    assert_eq!(second.server.job_id.as_deref(), Some("9f8e7d6c-5b4a-3928-1706-f5e4d3c2b1a0"));
+    assert!(second.started_at > first.started_at);
+}
+
+#[test]
+fn launching_then_joining_is_one_session() {
+    let mut tracker = SessionTracker::new(RobloxType::Player);
+    let launching = started(tracker.apply(&LogEvent::JoinStarted {
+        place_id: PlaceId(1111111111),
+        job_id: None,
+    }));
+
+    let joined = match tracker.apply(&join(1111111111, "0a1b2c3d-4e5f-6a7b-8c9d-0e1f2a3b4c5d")) {
+        Some(SessionChange::Updated(session)) => session,
+        other => panic!("expected the same session, got {:?}", other),
+    };
+
+    assert_eq!(joined.started_at, launching.started_at);
+    assert_eq!(joined.server.job_id.as_deref(), Some("0a1b2c3d-4e5f-6a7b-8c9d-0e1f2a3b4c5d"));
+    assert_eq!(tracker.apply(&join(1111111111, "0a1b2c3d-4e5f-6a7b-8c9d-0e1f2a3b4c5d")), None);
+}
+
+#[test]
+fn reserved_server_teleport_line_is_a_teleport() {
+    let registry = PatternRegistry::builtin();
+    let line = "2024-05-04T15:05:30.000Z,330.000000,3a4b,6 [FLog::GameJoinUtil] GameJoinUtil::initiateTeleportToReservedServer";
+
+    assert_eq!(
+        registry.parse_line(line),
+        Some(LogEvent::Teleport {
+            kind: ServerKind::Reserved
+        })
+    );
+}
+
+#[test]
+fn access_code_of_a_reserved_join_is_not_a_private_server() {
+    let registry = PatternRegistry::builtin();
+    let line = r#"2024-05-04T15:05:30.500Z,330.500000,3a4b,6 [FLog::GameJoinUtil] GameJoinUtil::joinGamePostReservedServer: POST {"placeId":9999999999,"accessCode":"abcdef01-2345-6789-abcd-ef0123456789"}"#;
+
+    assert_eq!(
+        registry.parse_line(line),
+        Some(LogEvent::ServerKindDetected {
+            kind: ServerKind::Reserved,
+            access_code: Some("abcdef01-2345-6789-abcd-ef0123456789".to_string()),
+        })
+    );
+}
+
+#[test]
+fn private_server_join_keeps_its_access_code() {
+    let registry = PatternRegistry::builtin();
+    let line = r#"2024-05-03T09:00:00.000Z,0.000000,9c0d,6 [FLog::GameJoinUtil] GameJoinUtil::joinGamePostPrivateServer: POST {"placeId":7777777777,"accessCode":"abcdef01-2345-6789-abcd-ef0123456789"}"#;
+
+    assert_eq!(registry.parse_line(line), Some(private_server_marker()));
+}
+
+#[test]
+fn reserved_server_teleport_keeps_the_session_going() {
+    let mut tracker = SessionTracker::new(RobloxType::Player);
+    let lobby = started(tracker.apply(&join(8888888888, "12121212-3434-5656-7878-909090909090")));
+
+    tracker.apply(&LogEvent::Teleport {
+        kind: ServerKind::Reserved,
+    });
+    assert_eq!(tracker.apply(&LogEvent::Disconnected), None);
+    let match_server = teleported(tracker.apply(&join(9999999999, "abababab-cdcd-efef-abab-cdcdcdcdcdcd")));
+
+    assert_eq!(match_server.server.kind, ServerKind::Reserved);
+    assert_eq!(match_server.started_at, lobby.started_at);
+    assert_eq!(match_server.join_link(), None);
+}
+
+#[test]
+fn disconnect_after_a_failed_teleport_ends_the_session() {
+    let timeout = Duration::from_millis(100);
+    let mut tracker = SessionTracker::new(RobloxType::Player).with_teleport_timeout(timeout);
+    started(tracker.apply(&join(3333333333, "11111111-2222-3333-4444-555555555555")));
+
+    tracker.apply(&LogEvent::Teleport {
+        kind: ServerKind::Public,
+    });
+    // The teleport failed, and the player is kicked later on
+    thread::sleep(timeout);
+
+    assert_eq!(tracker.apply(&LogEvent::Disconnected), Some(SessionChange::Ended));
+    assert_eq!(tracker.current(), None);
+}
+
+#[test]
+fn join_after_a_failed_teleport_is_a_new_session() {
+    let timeout = Duration::from_millis(100);
+    let mut tracker = SessionTracker::new(RobloxType::Player).with_teleport_timeout(timeout);
+    started(tracker.apply(&join(3333333333, "11111111-2222-3333-4444-555555555555")));
+
+    tracker.apply(&LogEvent::Teleport {
+        kind: ServerKind::Public,
+    });
+    thread::sleep(timeout);
+
+    started(tracker.apply(&join(5555555555, "66666666-7777-8888-9999-000000000000")));
+}
+
+#[test]
+fn marker_of_a_failed_join_does_not_stick_to_the_next_one() {
+    let mut tracker = SessionTracker::new(RobloxType::Player);
+
+    tracker.apply(&private_server_marker());
+    // Joining the private server failed and the app went back home
+    tracker.apply(&LogEvent::ReturnedToHome);
+    let session = started(tracker.apply(&join(1111111111, "0a1b2c3d-4e5f-6a7b-8c9d-0e1f2a3b4c5d")));
+
+    assert_eq!(session.server.kind, ServerKind::Public);
+    assert_eq!(session.server.access_code, None);
+    assert!(session.join_link().is_some());
+}
+
+#[test]
+fn teleport_replaces_an_earlier_marker() {
+    let mut tracker = SessionTracker::new(RobloxType::Player);
+    started(tracker.apply(&join(3333333333, "11111111-2222-3333-4444-555555555555")));
+
+    tracker.apply(&private_server_marker());
+    tracker.apply(&LogEvent::Teleport {
+        kind: ServerKind::Public,
+    });
+    tracker.apply(&LogEvent::Disconnected);
+    let session = teleported(tracker.apply(&join(5555555555, "66666666-7777-8888-9999-000000000000")));
+
+    assert_eq!(session.server.kind, ServerKind::Public);
+}
+
+#[test]
+fn public_servers_are_not_private() {
+    let mut tracker = SessionTracker::new(RobloxType::Player);
+    let session = started(tracker.apply(&join(3333333333, "11111111-2222-3333-4444-555555555555")));
+
+    assert!(!session.is_private());
+}
+
+#[test]
+fn teleport_into_a_private_server_is_private() {
+    let mut tracker = SessionTracker::new(RobloxType::Player);
+    started(tracker.apply(&join(3333333333, "11111111-2222-3333-4444-555555555555")));
+
+    tracker.apply(&LogEvent::Teleport {
+        kind: ServerKind::Public,
+    });
+    tracker.apply(&LogEvent::Disconnected);
+    tracker.apply(&private_server_marker());
+    let session = teleported(tracker.apply(&join(7777777777, "fedcba98-7654-3210-fedc-ba9876543210")));
+
+    assert!(session.is_private());
+    assert_eq!(session.join_link(), None);
+}
+
+#[test]
+fn closing_the_place_in_studio_ends_the_session() {
+    let mut tracker = SessionTracker::new(RobloxType::Studio);
+    started(tracker.apply(&LogEvent::StudioPlaceOpened {
+        game_id: GameId::Place(PlaceId(1313131313)),
+    }));
+
+    assert_eq!(tracker.apply(&LogEvent::StudioPlaceClosed), Some(SessionChange::Ended));
+    assert_eq!(tracker.current(), None);
+}
+
+#[test]
+fn studio_place_close_is_read_from_custom_patterns() {
+    let registry = PatternRegistry::new(&[LogPattern {
+        event: EventKind::StudioPlaceClosed,
+        regex: r"Closing place (?P<place_id>\d+)".to_string(),
+    }])
+    .unwrap();
+
+    assert_eq!(
+        registry.parse_line("[FLog::Studio] Closing place 1313131313"),
+        Some(LogEvent::StudioPlaceClosed)
+    );
+}